@@ -0,0 +1,395 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+use crate::disassembler::Line;
+use crate::opcode::{decode, Operation};
+
+/// How control gets from the end of one block to the start of another.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EdgeKind {
+    /// Running on into the next instruction, including returning from a call.
+    Next,
+    Jump,
+    /// Skipping the next instruction.
+    Skip,
+    Call,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Edge {
+    pub target: u16,
+    pub kind: EdgeKind,
+}
+
+/// Instructions that always run one after the other, from `start` up to `end`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Block {
+    pub start: u16,
+
+    /// Address after the last instruction of the block.
+    pub end: u16,
+
+    /// Blocks control can go to next. None for returns, exits and computed jumps.
+    pub exits: Vec<Edge>,
+}
+
+/// Control-flow graph of a ROM, found by following every path from its first instruction.
+///
+/// Only bytes on one of those paths are taken to be code, the rest are data. BNNN jumps to
+/// an address that depends on V0, so paths through it are not followed; its address is
+/// noted among the computed jumps instead, and code only reached that way counts as data.
+#[derive(Debug, Clone)]
+pub struct Analysis {
+    base: u16,
+    instructions: BTreeMap<u16, (Operation, u16)>,
+    blocks: BTreeMap<u16, Block>,
+    subroutines: BTreeSet<u16>,
+    computed_jumps: BTreeSet<u16>,
+}
+
+impl Analysis {
+    /// Analyses `bytes` loaded at `base`, starting at `base`.
+    pub fn new(bytes: &[u8], base: u16) -> Self {
+        let mut analysis = Analysis {
+            base,
+            instructions: BTreeMap::new(),
+            blocks: BTreeMap::new(),
+            subroutines: BTreeSet::new(),
+            computed_jumps: BTreeSet::new(),
+        };
+        let mut leaders = BTreeSet::from([base]);
+        let mut pending = vec![base];
+
+        while let Some(address) = pending.pop() {
+            if analysis.instructions.contains_key(&address) {
+                continue;
+            }
+            let Some((operation, len)) = instruction_at(bytes, base, address) else {
+                continue;
+            };
+            analysis.instructions.insert(address, (operation, len));
+
+            let exits = exits(bytes, base, address, &operation, len);
+            match operation {
+                Operation::SubroutineCall { nnn } => {
+                    analysis.subroutines.insert(nnn);
+                }
+                Operation::GotoAddressWithRegister { .. } => {
+                    analysis.computed_jumps.insert(address);
+                }
+                _ => {}
+            }
+            if !is_straight(&exits, address.wrapping_add(len)) {
+                leaders.extend(exits.iter().map(|edge| edge.target));
+            }
+            pending.extend(exits.iter().map(|edge| edge.target));
+        }
+
+        for &start in &leaders {
+            if let Some(block) = analysis.block_from(bytes, start, &leaders) {
+                analysis.blocks.insert(start, block);
+            }
+        }
+        analysis
+    }
+
+    pub fn blocks(&self) -> impl Iterator<Item = &Block> {
+        self.blocks.values()
+    }
+
+    /// The block starting at `address`, if there is one.
+    pub fn block(&self, address: u16) -> Option<&Block> {
+        self.blocks.get(&address)
+    }
+
+    /// Addresses called as subroutines.
+    pub fn subroutines(&self) -> impl Iterator<Item = u16> + '_ {
+        self.subroutines.iter().copied()
+    }
+
+    /// Addresses of BNNN instructions, whose targets are not known.
+    pub fn computed_jumps(&self) -> impl Iterator<Item = u16> + '_ {
+        self.computed_jumps.iter().copied()
+    }
+
+    /// Whether the byte at `address` is part of a reachable instruction.
+    pub fn is_code(&self, address: u16) -> bool {
+        self.instructions
+            .range(..=address)
+            .next_back()
+            .is_some_and(|(start, (_, len))| address - start < *len)
+    }
+
+    /// Lists `bytes` with reachable instructions decoded and everything else as data.
+    pub fn disassemble(&self, bytes: &[u8]) -> Vec<Line> {
+        let mut lines = Vec::new();
+        let mut offset = 0;
+
+        while offset < bytes.len() {
+            let address = self.base.wrapping_add(offset as u16);
+            let (len, operation) = match self.instructions.get(&address) {
+                Some((operation, len)) => (*len as usize, Some(*operation)),
+                // Data is listed two bytes at a time, up to the next instruction.
+                None => (
+                    (1..=2)
+                        .take_while(|len| {
+                            offset + len <= bytes.len()
+                                && !self
+                                    .instructions
+                                    .contains_key(&address.wrapping_add(*len as u16 - 1))
+                        })
+                        .last()
+                        .unwrap_or(1),
+                    None,
+                ),
+            };
+
+            lines.push(Line {
+                address,
+                bytes: bytes[offset..offset + len].to_vec(),
+                operation,
+            });
+            offset += len;
+        }
+
+        lines
+    }
+
+    /// The graph in the Graphviz DOT language, with the instructions of each block.
+    pub fn dot(&self) -> Dot<'_> {
+        Dot { analysis: self }
+    }
+
+    /// Follows the instructions from `start` until control can go anywhere but the next
+    /// one, or the next one starts another block.
+    fn block_from(&self, bytes: &[u8], start: u16, leaders: &BTreeSet<u16>) -> Option<Block> {
+        self.instructions.get(&start)?;
+        let mut address = start;
+
+        loop {
+            let (operation, len) = self.instructions[&address];
+            let end = address.wrapping_add(len);
+            let exits: Vec<_> = exits(bytes, self.base, address, &operation, len)
+                .into_iter()
+                .filter(|edge| self.instructions.contains_key(&edge.target))
+                .collect();
+
+            if is_straight(&exits, end) && !leaders.contains(&end) {
+                address = end;
+                continue;
+            }
+            return Some(Block { start, end, exits });
+        }
+    }
+}
+
+/// The instruction at `address` and its length in bytes, if there is a valid one.
+fn instruction_at(bytes: &[u8], base: u16, address: u16) -> Option<(Operation, u16)> {
+    let offset = address.checked_sub(base)? as usize;
+    let word = bytes.get(offset..offset + 2)?;
+
+    match decode(u16::from_be_bytes([word[0], word[1]])).ok()? {
+        Operation::SetIndexToLongAddress if offset + 4 > bytes.len() => None,
+        Operation::SetIndexToLongAddress => Some((Operation::SetIndexToLongAddress, 4)),
+        operation => Some((operation, 2)),
+    }
+}
+
+/// Where control can go after the instruction at `address`.
+fn exits(bytes: &[u8], base: u16, address: u16, operation: &Operation, len: u16) -> Vec<Edge> {
+    let next = address.wrapping_add(len);
+    let edge = |target, kind| Edge { target, kind };
+
+    match *operation {
+        Operation::GotoAddress { nnn } => vec![edge(nnn, EdgeKind::Jump)],
+        Operation::SubroutineCall { nnn } => {
+            vec![edge(nnn, EdgeKind::Call), edge(next, EdgeKind::Next)]
+        }
+        Operation::EqualityCheck { .. }
+        | Operation::InequalityCheck { .. }
+        | Operation::EqualityRegisterCheck { .. }
+        | Operation::InequalityRegisterCheck { .. }
+        | Operation::SkipIfKeyPressed { .. }
+        | Operation::SkipIfKeyNotPressed { .. } => {
+            // Skips step over both words of F000.
+            let skipped = match instruction_at(bytes, base, next) {
+                Some((Operation::SetIndexToLongAddress, _)) => 4,
+                _ => 2,
+            };
+            vec![
+                edge(next, EdgeKind::Next),
+                edge(next.wrapping_add(skipped), EdgeKind::Skip),
+            ]
+        }
+        Operation::SubroutineReturn
+        | Operation::Exit
+        | Operation::GotoAddressWithRegister { .. } => Vec::new(),
+        _ => vec![edge(next, EdgeKind::Next)],
+    }
+}
+
+/// Whether control only runs on into the next instruction.
+fn is_straight(exits: &[Edge], next: u16) -> bool {
+    matches!(exits, [Edge { target, kind: EdgeKind::Next }] if *target == next)
+}
+
+/// An Analysis written out as a Graphviz digraph.
+///
+/// Subroutines are drawn with a double border and blocks ending in a computed jump in red.
+pub struct Dot<'a> {
+    analysis: &'a Analysis,
+}
+
+impl fmt::Display for Dot<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let analysis = self.analysis;
+
+        writeln!(f, "digraph chip8 {{")?;
+        writeln!(f, "    node [shape=box, fontname=\"monospace\"];")?;
+
+        for block in analysis.blocks() {
+            write!(f, "    \"{:#05X}\" [label=\"", block.start)?;
+            for (address, (operation, _)) in analysis.instructions.range(block.start..block.end) {
+                write!(f, "{:#05X}  {}\\l", address, operation)?;
+            }
+            write!(f, "\"")?;
+
+            if analysis.subroutines.contains(&block.start) {
+                write!(f, ", peripheries=2")?;
+            }
+            if analysis
+                .computed_jumps
+                .range(block.start..block.end)
+                .next()
+                .is_some()
+            {
+                write!(f, ", color=red")?;
+            }
+            writeln!(f, "];")?;
+        }
+
+        for block in analysis.blocks() {
+            for edge in &block.exits {
+                let style = match edge.kind {
+                    EdgeKind::Next => "",
+                    EdgeKind::Jump => " [label=\"jump\"]",
+                    EdgeKind::Skip => " [label=\"skip\", style=dashed]",
+                    EdgeKind::Call => " [label=\"call\", style=bold]",
+                };
+                writeln!(
+                    f,
+                    "    \"{:#05X}\" -> \"{:#05X}\"{};",
+                    block.start, edge.target, style
+                )?;
+            }
+        }
+
+        writeln!(f, "}}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::assemble;
+    use crate::disassembler::Syntax;
+
+    const PROGRAM: &str = "
+        start:  CALL draw
+                SE V0, 0
+                JP done
+                LD V1, 1
+        done:   JP done
+        draw:   LD I, sprite
+                DRW V2, V3, 2
+                JP V0, table
+        table:  RET
+        sprite: DB 0x3C, 0x42
+    ";
+
+    fn analyse() -> (Vec<u8>, Analysis) {
+        let rom = assemble(PROGRAM).unwrap();
+        let analysis = Analysis::new(&rom, 0x200);
+        (rom, analysis)
+    }
+
+    #[test]
+    fn test_blocks() {
+        let (_, analysis) = analyse();
+        let starts: Vec<_> = analysis.blocks().map(|block| block.start).collect();
+
+        assert_eq!(starts, [0x200, 0x202, 0x204, 0x206, 0x208, 0x20A]);
+        assert_eq!(
+            analysis.block(0x202).unwrap().exits,
+            [
+                Edge {
+                    target: 0x204,
+                    kind: EdgeKind::Next
+                },
+                Edge {
+                    target: 0x206,
+                    kind: EdgeKind::Skip
+                },
+            ]
+        );
+        assert_eq!(analysis.block(0x20A).unwrap().end, 0x210);
+        assert!(analysis.block(0x20A).unwrap().exits.is_empty());
+        assert_eq!(analysis.subroutines().collect::<Vec<_>>(), [0x20A]);
+        assert_eq!(analysis.computed_jumps().collect::<Vec<_>>(), [0x20E]);
+    }
+
+    #[test]
+    fn test_tells_code_from_data() {
+        let (rom, analysis) = analyse();
+
+        assert!(analysis.is_code(0x20F));
+        // Only reached through the computed jump.
+        assert!(!analysis.is_code(0x210));
+
+        let lines = analysis.disassemble(&rom);
+        let listing: Vec<_> = lines
+            .iter()
+            .skip(7)
+            .map(|line| line.display(Syntax::Cowgod).to_string())
+            .collect();
+        assert_eq!(
+            listing,
+            [
+                "0x20E  B2 10        JP V0, 0x210",
+                "0x210  00 EE        DB 0x00, 0xEE",
+                "0x212  3C 42        DB 0x3C, 0x42",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_skips_over_long_loads() {
+        let rom = [0x30, 0x00, 0xF0, 0x00, 0x12, 0x34, 0x00, 0xFD];
+        let analysis = Analysis::new(&rom, 0x200);
+
+        assert_eq!(
+            analysis.block(0x200).unwrap().exits[1],
+            Edge {
+                target: 0x206,
+                kind: EdgeKind::Skip
+            }
+        );
+        assert!(analysis.is_code(0x205));
+        assert_eq!(analysis.block(0x202).unwrap().end, 0x206);
+    }
+
+    #[test]
+    fn test_dot() {
+        let (_, analysis) = analyse();
+        let dot = analysis.dot().to_string();
+
+        assert!(dot.starts_with("digraph chip8 {\n"));
+        assert!(dot.contains(
+            "    \"0x20A\" [label=\"0x20A  LD I, 0x212\\l0x20C  DRW V2, V3, 2\\l0x20E  JP V0, 0x210\\l\", peripheries=2, color=red];\n"
+        ));
+        assert!(dot.contains("    \"0x200\" -> \"0x20A\" [label=\"call\", style=bold];\n"));
+        assert!(dot.contains("    \"0x200\" -> \"0x202\";\n"));
+        assert!(dot.contains("    \"0x202\" -> \"0x206\" [label=\"skip\", style=dashed];\n"));
+        assert!(dot.ends_with("}\n"));
+    }
+}
@@ -0,0 +1,625 @@
+use std::collections::HashMap;
+
+use crate::constants::PROGRAM_ADDRESS;
+use crate::error::{AssemblyError, AssemblyErrorKind};
+use crate::opcode::{encode, Operation};
+
+/// Assembles a program written with Cowgod's mnemonics into a ROM loaded at 0x200.
+///
+/// Each line holds an optional `label:`, then an optional instruction or directive, then
+/// an optional `; comment`. Mnemonics, registers and keywords are case insensitive.
+/// Numbers are decimal, or hexadecimal and binary with a `0x` or `0b` prefix, and a
+/// label can be used wherever an address is expected.
+///
+/// Besides the instructions, `DB` and `DW` emit bytes and big endian words of data.
+///
+/// ```text
+/// loop:
+///     LD V0, 0x0A     ; count down from ten
+///     CALL wait
+///     JP loop
+/// wait:
+///     DB 0x00, 0xEE
+/// ```
+pub fn assemble(source: &str) -> Result<Vec<u8>, AssemblyError> {
+    assemble_at(source, PROGRAM_ADDRESS)
+}
+
+/// Assembles a program, resolving labels as if it were loaded at `base`.
+pub fn assemble_at(source: &str, base: u16) -> Result<Vec<u8>, AssemblyError> {
+    let statements = source
+        .lines()
+        .enumerate()
+        .map(|(i, line)| parse_line(i + 1, line))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    // The first pass places the labels, so they can be referred to before they appear.
+    let mut labels = HashMap::new();
+    let mut address = base as usize;
+    for statement in &statements {
+        if let Some(label) = &statement.label {
+            if labels.insert(label.text, address).is_some() {
+                return Err(label.error(AssemblyErrorKind::DuplicateLabel(label.text.to_string())));
+            }
+        }
+        if let Some(instruction) = &statement.instruction {
+            address += instruction.size();
+        }
+    }
+
+    let mut rom = Vec::new();
+    for instruction in statements.iter().filter_map(|s| s.instruction.as_ref()) {
+        instruction.emit(&labels, &mut rom)?;
+    }
+    Ok(rom)
+}
+
+/// A piece of source text, remembering where it was found for error reporting.
+#[derive(Debug, Clone, Copy)]
+struct Span<'a> {
+    text: &'a str,
+    line: usize,
+    column: usize,
+}
+
+impl<'a> Span<'a> {
+    fn error(&self, kind: AssemblyErrorKind) -> AssemblyError {
+        AssemblyError {
+            line: self.line,
+            column: self.column,
+            kind,
+        }
+    }
+
+    /// The span with whitespace removed from both ends.
+    fn trim(&self) -> Span<'a> {
+        let start = self.text.len() - self.text.trim_start().len();
+        Span {
+            text: self.text.trim(),
+            line: self.line,
+            column: self.column + start,
+        }
+    }
+
+    fn split_at(&self, mid: usize) -> (Span<'a>, Span<'a>) {
+        let (left, right) = self.text.split_at(mid);
+        (
+            Span {
+                text: left,
+                ..*self
+            },
+            Span {
+                text: right,
+                column: self.column + mid,
+                ..*self
+            },
+        )
+    }
+
+    fn split(&self, separator: char) -> Vec<Span<'a>> {
+        let mut parts = Vec::new();
+        let mut rest = *self;
+        while let Some(position) = rest.text.find(separator) {
+            let (part, remainder) = rest.split_at(position);
+            parts.push(part.trim());
+            rest = remainder.split_at(separator.len_utf8()).1;
+        }
+        parts.push(rest.trim());
+        parts
+    }
+}
+
+struct Statement<'a> {
+    label: Option<Span<'a>>,
+    instruction: Option<Instruction<'a>>,
+}
+
+struct Instruction<'a> {
+    mnemonic: Span<'a>,
+    operands: Vec<Operand<'a>>,
+}
+
+/// Names with a special meaning as operands, so labels with these names can not be used.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Keyword {
+    I,
+    IndirectI,
+    DelayTimer,
+    SoundTimer,
+    Key,
+    Font,
+    BigFont,
+    Bcd,
+    Flags,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Literal<'a> {
+    Number(u32),
+    Label(&'a str),
+}
+
+#[derive(Debug, Clone, Copy)]
+enum OperandKind<'a> {
+    Register(u8),
+    RegisterRange(u8, u8),
+    Reserved(Keyword),
+    Long(Literal<'a>),
+    Value(Literal<'a>),
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Operand<'a> {
+    span: Span<'a>,
+    kind: OperandKind<'a>,
+}
+
+fn parse_line(line: usize, text: &str) -> Result<Statement<'_>, AssemblyError> {
+    let mut rest = Span {
+        text,
+        line,
+        column: 1,
+    };
+    if let Some(comment) = rest.text.find(';') {
+        rest = rest.split_at(comment).0;
+    }
+    rest = rest.trim();
+
+    let mut label = None;
+    if let Some(colon) = rest.text.find(':') {
+        let (name, remainder) = rest.split_at(colon);
+        let name = name.trim();
+        if !is_identifier(name.text) {
+            return Err(name.error(AssemblyErrorKind::InvalidLabel(name.text.to_string())));
+        }
+        label = Some(name);
+        rest = remainder.split_at(1).1.trim();
+    }
+
+    if rest.text.is_empty() {
+        return Ok(Statement {
+            label,
+            instruction: None,
+        });
+    }
+
+    let end = rest
+        .text
+        .find(char::is_whitespace)
+        .unwrap_or(rest.text.len());
+    let (mnemonic, operands) = rest.split_at(end);
+    let operands = operands.trim();
+    let operands = if operands.text.is_empty() {
+        Vec::new()
+    } else {
+        operands
+            .split(',')
+            .into_iter()
+            .map(parse_operand)
+            .collect::<Result<_, _>>()?
+    };
+
+    Ok(Statement {
+        label,
+        instruction: Some(Instruction { mnemonic, operands }),
+    })
+}
+
+fn parse_operand(span: Span) -> Result<Operand, AssemblyError> {
+    let upper = span.text.to_ascii_uppercase();
+    let keyword = match upper.as_str() {
+        "I" => Some(Keyword::I),
+        "[I]" => Some(Keyword::IndirectI),
+        "DT" => Some(Keyword::DelayTimer),
+        "ST" => Some(Keyword::SoundTimer),
+        "K" => Some(Keyword::Key),
+        "F" => Some(Keyword::Font),
+        "HF" => Some(Keyword::BigFont),
+        "B" => Some(Keyword::Bcd),
+        "R" => Some(Keyword::Flags),
+        _ => None,
+    };
+
+    let kind = if let Some(keyword) = keyword {
+        OperandKind::Reserved(keyword)
+    } else if let Some(register) = parse_register(&upper) {
+        OperandKind::Register(register)
+    } else if let Some((x, y)) = upper
+        .split_once('-')
+        .and_then(|(x, y)| Some((parse_register(x.trim())?, parse_register(y.trim())?)))
+    {
+        OperandKind::RegisterRange(x, y)
+    } else if upper.starts_with("LONG ") {
+        let value = span.split_at(4).1.trim();
+        OperandKind::Long(parse_literal(value)?)
+    } else {
+        OperandKind::Value(parse_literal(span)?)
+    };
+
+    Ok(Operand { span, kind })
+}
+
+fn parse_register(text: &str) -> Option<u8> {
+    match text.as_bytes() {
+        [b'V', digit] => (*digit as char).to_digit(16).map(|x| x as u8),
+        _ => None,
+    }
+}
+
+fn parse_literal(span: Span) -> Result<Literal, AssemblyError> {
+    let text = span.text;
+    let lower = text.to_ascii_lowercase();
+
+    let number = if let Some(hex) = lower.strip_prefix("0x") {
+        u32::from_str_radix(hex, 16)
+    } else if let Some(binary) = lower.strip_prefix("0b") {
+        u32::from_str_radix(binary, 2)
+    } else if text.starts_with(|c: char| c.is_ascii_digit()) {
+        text.parse()
+    } else if is_identifier(text) {
+        return Ok(Literal::Label(text));
+    } else {
+        return Err(span.error(AssemblyErrorKind::InvalidOperand(text.to_string())));
+    };
+
+    number
+        .map(Literal::Number)
+        .map_err(|_| span.error(AssemblyErrorKind::InvalidNumber(text.to_string())))
+}
+
+fn is_identifier(text: &str) -> bool {
+    text.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && text.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+impl<'a> Instruction<'a> {
+    /// Bytes the instruction takes up, which has to be known before labels are resolved.
+    fn size(&self) -> usize {
+        match self.mnemonic.text.to_ascii_uppercase().as_str() {
+            "DB" => self.operands.len(),
+            "DW" => self.operands.len() * 2,
+            _ if self
+                .operands
+                .iter()
+                .any(|operand| matches!(operand.kind, OperandKind::Long(_))) =>
+            {
+                4
+            }
+            _ => 2,
+        }
+    }
+
+    fn emit(&self, labels: &HashMap<&str, usize>, rom: &mut Vec<u8>) -> Result<(), AssemblyError> {
+        use OperandKind::*;
+
+        let value = |operand: &Operand, max: u32| -> Result<u32, AssemblyError> {
+            let literal = match operand.kind {
+                Value(literal) | Long(literal) => literal,
+                _ => return Err(self.invalid_operands()),
+            };
+            let number = match literal {
+                Literal::Number(number) => number,
+                Literal::Label(label) => match labels.get(label) {
+                    Some(address) => *address as u32,
+                    None => {
+                        return Err(operand
+                            .span
+                            .error(AssemblyErrorKind::UnknownLabel(label.to_string())))
+                    }
+                },
+            };
+            if number > max {
+                return Err(operand
+                    .span
+                    .error(AssemblyErrorKind::OutOfRange { value: number, max }));
+            }
+            Ok(number)
+        };
+        let address = |operand: &Operand| value(operand, 0xFFF).map(|nnn| nnn as u16);
+        let byte = |operand: &Operand| value(operand, 0xFF).map(|nn| nn as u8);
+        let nibble = |operand: &Operand| value(operand, 0xF).map(|n| n as u8);
+
+        let mnemonic = self.mnemonic.text.to_ascii_uppercase();
+        let operands = &self.operands[..];
+        let kinds: Vec<_> = operands.iter().map(|operand| operand.kind).collect();
+
+        match mnemonic.as_str() {
+            "DB" => {
+                for operand in operands {
+                    rom.push(byte(operand)?);
+                }
+                return Ok(());
+            }
+            "DW" => {
+                for operand in operands {
+                    rom.extend((value(operand, 0xFFFF)? as u16).to_be_bytes());
+                }
+                return Ok(());
+            }
+            _ => {}
+        }
+
+        let operation = match (mnemonic.as_str(), &kinds[..]) {
+            ("SYS", [Value(_)]) => {
+                // Machine code routines can not be called, so only the no-op is allowed.
+                value(&operands[0], 0)?;
+                Operation::NoOperation
+            }
+            ("CLS", []) => Operation::ClearDisplay,
+            ("RET", []) => Operation::SubroutineReturn,
+            ("JP", [Value(_)]) => Operation::GotoAddress {
+                nnn: address(&operands[0])?,
+            },
+            ("JP", [Register(0), Value(_)]) => Operation::GotoAddressWithRegister {
+                nnn: address(&operands[1])?,
+            },
+            ("CALL", [Value(_)]) => Operation::SubroutineCall {
+                nnn: address(&operands[0])?,
+            },
+            ("SE", [Register(x), Value(_)]) => Operation::EqualityCheck {
+                x: *x,
+                nn: byte(&operands[1])?,
+            },
+            ("SE", [Register(x), Register(y)]) => Operation::EqualityRegisterCheck { x: *x, y: *y },
+            ("SNE", [Register(x), Value(_)]) => Operation::InequalityCheck {
+                x: *x,
+                nn: byte(&operands[1])?,
+            },
+            ("SNE", [Register(x), Register(y)]) => {
+                Operation::InequalityRegisterCheck { x: *x, y: *y }
+            }
+            ("LD", [Register(x), Value(_)]) => Operation::SetRegister {
+                x: *x,
+                nn: byte(&operands[1])?,
+            },
+            ("LD", [Register(x), Register(y)]) => {
+                Operation::SetRegisterFromRegister { x: *x, y: *y }
+            }
+            ("LD", [Reserved(Keyword::I), Value(_)]) => Operation::SetIndexToAddress {
+                nnn: address(&operands[1])?,
+            },
+            ("LD", [Reserved(Keyword::I), Long(_)]) => {
+                let long = value(&operands[1], 0xFFFF)? as u16;
+                rom.extend(encode_checked(&Operation::SetIndexToLongAddress).to_be_bytes());
+                rom.extend(long.to_be_bytes());
+                return Ok(());
+            }
+            ("LD", [Register(x), Reserved(Keyword::DelayTimer)]) => {
+                Operation::GetDelayTimer { x: *x }
+            }
+            ("LD", [Register(x), Reserved(Keyword::Key)]) => Operation::StoreNextKeypress { x: *x },
+            ("LD", [Reserved(Keyword::DelayTimer), Register(x)]) => {
+                Operation::SetDelayTimer { x: *x }
+            }
+            ("LD", [Reserved(Keyword::SoundTimer), Register(x)]) => {
+                Operation::SetSoundTimer { x: *x }
+            }
+            ("LD", [Reserved(Keyword::Font), Register(x)]) => Operation::SetIndexToSprite { x: *x },
+            ("LD", [Reserved(Keyword::BigFont), Register(x)]) => {
+                Operation::SetIndexToLargeSprite { x: *x }
+            }
+            ("LD", [Reserved(Keyword::Bcd), Register(x)]) => {
+                Operation::StoreBinaryCodedDecimal { x: *x }
+            }
+            ("LD", [Reserved(Keyword::IndirectI), Register(x)]) => {
+                Operation::StoreRegistersInMemory { x: *x }
+            }
+            ("LD", [Register(x), Reserved(Keyword::IndirectI)]) => {
+                Operation::SetRegistersFromMemory { x: *x }
+            }
+            ("LD", [Reserved(Keyword::Flags), Register(x)]) => {
+                Operation::StoreRegistersInFlags { x: *x }
+            }
+            ("LD", [Register(x), Reserved(Keyword::Flags)]) => {
+                Operation::SetRegistersFromFlags { x: *x }
+            }
+            ("LD", [Reserved(Keyword::IndirectI), RegisterRange(x, y)]) => {
+                Operation::StoreRegisterRange { x: *x, y: *y }
+            }
+            ("LD", [RegisterRange(x, y), Reserved(Keyword::IndirectI)]) => {
+                Operation::LoadRegisterRange { x: *x, y: *y }
+            }
+            ("ADD", [Register(x), Value(_)]) => Operation::AddRegister {
+                x: *x,
+                nn: byte(&operands[1])?,
+            },
+            ("ADD", [Register(x), Register(y)]) => Operation::AddValues { x: *x, y: *y },
+            ("ADD", [Reserved(Keyword::I), Register(x)]) => Operation::AddToIndex { x: *x },
+            ("OR", [Register(x), Register(y)]) => Operation::BitwiseOr { x: *x, y: *y },
+            ("AND", [Register(x), Register(y)]) => Operation::BitwiseAnd { x: *x, y: *y },
+            ("XOR", [Register(x), Register(y)]) => Operation::BitwiseXor { x: *x, y: *y },
+            ("SUB", [Register(x), Register(y)]) => Operation::SubtractValues { x: *x, y: *y },
+            ("SUBN", [Register(x), Register(y)]) => {
+                Operation::SubtractValueFromRegister { x: *x, y: *y }
+            }
+            ("SHR", [Register(x)]) => Operation::StoreLeastSignificant { x: *x, y: *x },
+            ("SHR", [Register(x), Register(y)]) => {
+                Operation::StoreLeastSignificant { x: *x, y: *y }
+            }
+            ("SHL", [Register(x)]) => Operation::StoreMostSignificant { x: *x, y: *x },
+            ("SHL", [Register(x), Register(y)]) => Operation::StoreMostSignificant { x: *x, y: *y },
+            ("RND", [Register(x), Value(_)]) => Operation::AssignRandomNumber {
+                x: *x,
+                nn: byte(&operands[1])?,
+            },
+            ("DRW", [Register(x), Register(y), Value(_)]) => Operation::DrawSprite {
+                x: *x,
+                y: *y,
+                n: nibble(&operands[2])?,
+            },
+            ("SKP", [Register(x)]) => Operation::SkipIfKeyPressed { x: *x },
+            ("SKNP", [Register(x)]) => Operation::SkipIfKeyNotPressed { x: *x },
+            ("SCD", [Value(_)]) => Operation::ScrollDown {
+                n: nibble(&operands[0])?,
+            },
+            ("SCU", [Value(_)]) => Operation::ScrollUp {
+                n: nibble(&operands[0])?,
+            },
+            ("SCR", []) => Operation::ScrollRight,
+            ("SCL", []) => Operation::ScrollLeft,
+            ("EXIT", []) => Operation::Exit,
+            ("LOW", []) => Operation::LowResolution,
+            ("HIGH", []) => Operation::HighResolution,
+            ("PLANE", [Value(_)]) => Operation::SelectPlanes {
+                x: nibble(&operands[0])?,
+            },
+            ("AUDIO", []) => Operation::StoreAudioPattern,
+            ("PITCH", [Register(x)]) => Operation::SetPitch { x: *x },
+            (
+                "SYS" | "CLS" | "RET" | "JP" | "CALL" | "SE" | "SNE" | "LD" | "ADD" | "OR" | "AND"
+                | "XOR" | "SUB" | "SUBN" | "SHR" | "SHL" | "RND" | "DRW" | "SKP" | "SKNP" | "SCD"
+                | "SCU" | "SCR" | "SCL" | "EXIT" | "LOW" | "HIGH" | "PLANE" | "AUDIO" | "PITCH",
+                _,
+            ) => return Err(self.invalid_operands()),
+            _ => {
+                return Err(self.mnemonic.error(AssemblyErrorKind::UnknownMnemonic(
+                    self.mnemonic.text.to_string(),
+                )))
+            }
+        };
+
+        rom.extend(encode_checked(&operation).to_be_bytes());
+        Ok(())
+    }
+
+    fn invalid_operands(&self) -> AssemblyError {
+        self.mnemonic.error(AssemblyErrorKind::InvalidOperands(
+            self.mnemonic.text.to_string(),
+        ))
+    }
+}
+
+/// Registers are single hex digits and values are range checked as they are parsed,
+/// so every operation the assembler builds can be encoded.
+fn encode_checked(operation: &Operation) -> u16 {
+    encode(operation).expect("operands are range checked while assembling")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disassembler::{disassemble, Syntax};
+
+    #[test]
+    fn test_assembles_labels_and_data() {
+        let rom = assemble(
+            "start:  LD I, sprite ; point at the data
+                     DRW V0, V1, 3
+             loop:   jp loop
+             sprite: DB 0b11000000, 0x60, 48
+                     DW start",
+        )
+        .unwrap();
+
+        assert_eq!(
+            rom,
+            [0xA2, 0x06, 0xD0, 0x13, 0x12, 0x04, 0xC0, 0x60, 0x30, 0x02, 0x00]
+        );
+    }
+
+    #[test]
+    fn test_round_trips_through_the_disassembler() {
+        let source = "
+            CLS
+            RET
+            JP 0x123
+            JP V0, 0x456
+            CALL 0x789
+            SE V1, 0x22
+            SNE VA, 0xBB
+            SE V1, V2
+            SNE V3, V4
+            LD V5, 0x66
+            ADD V7, 0x08
+            LD V1, V2
+            OR V1, V2
+            AND V1, V2
+            XOR V1, V2
+            ADD V1, V2
+            SUB V1, V2
+            SHR V1, V2
+            SUBN V1, V2
+            SHL V1, V2
+            LD I, 0xABC
+            RND VC, 0x0F
+            DRW V1, V2, 15
+            SKP V3
+            SKNP V4
+            LD V5, DT
+            LD V6, K
+            LD DT, V7
+            LD ST, V8
+            ADD I, V9
+            LD F, VA
+            LD B, VB
+            LD [I], VC
+            LD VD, [I]
+            SCD 4
+            SCR
+            SCL
+            EXIT
+            LOW
+            HIGH
+            LD HF, VE
+            LD R, V7
+            LD V7, R
+            SCU 3
+            LD [I], V2-V5
+            LD V5-V2, [I]
+            LD I, LONG 0x1234
+            PLANE 3
+            AUDIO
+            PITCH VF
+        ";
+        let rom = assemble(source).unwrap();
+
+        let lines = disassemble(&rom, PROGRAM_ADDRESS);
+        assert!(lines.iter().all(|line| line.operation.is_some()));
+        let listing: Vec<_> = lines
+            .iter()
+            .map(|line| line.display(Syntax::Cowgod).to_string()[20..].to_string())
+            .collect();
+
+        assert_eq!(assemble(&listing.join("\n")).unwrap(), rom);
+    }
+
+    #[test]
+    fn test_reports_error_positions() {
+        let error = |source| {
+            let error = assemble(source).unwrap_err();
+            (error.line, error.column, error.kind)
+        };
+
+        assert_eq!(
+            error("CLS\n  LD V0, 0x100"),
+            (
+                2,
+                10,
+                AssemblyErrorKind::OutOfRange {
+                    value: 0x100,
+                    max: 0xFF
+                }
+            )
+        );
+        assert_eq!(
+            error("  JP nowhere"),
+            (1, 6, AssemblyErrorKind::UnknownLabel("nowhere".to_string()))
+        );
+        assert_eq!(
+            error("a: CLS\na: RET"),
+            (2, 1, AssemblyErrorKind::DuplicateLabel("a".to_string()))
+        );
+        assert_eq!(
+            error(" MOV V0, V1"),
+            (1, 2, AssemblyErrorKind::UnknownMnemonic("MOV".to_string()))
+        );
+        assert_eq!(
+            error("DRW V0, 5"),
+            (1, 1, AssemblyErrorKind::InvalidOperands("DRW".to_string()))
+        );
+        assert_eq!(
+            error("LD V0, 0xZZ"),
+            (1, 8, AssemblyErrorKind::InvalidNumber("0xZZ".to_string()))
+        );
+    }
+}
@@ -0,0 +1,321 @@
+#[cfg(feature = "sdl")]
+mod window;
+
+use chip8::analysis::Analysis;
+use chip8::assembler::assemble;
+use chip8::constants::PROGRAM_ADDRESS;
+use chip8::disassembler::{disassemble, Syntax};
+use chip8::opcode::decode;
+use chip8::trace::{first_divergence, parse_trace, TraceRecord};
+use clap::{Parser, Subcommand, ValueEnum};
+use std::fs;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+
+#[derive(Parser)]
+#[clap(author, version, about, long_about = None)]
+#[clap(subcommand_negates_reqs = true)]
+// Most options are only read when running a ROM, which needs SDL.
+#[cfg_attr(not(feature = "sdl"), allow(dead_code))]
+struct Args {
+    /// ROM to run
+    #[clap(value_parser, required = true)]
+    rom: Option<String>,
+
+    #[clap(subcommand)]
+    command: Option<Command>,
+
+    /// Instructions per 60 Hz frame, unless timed like the VIP
+    #[clap(short, long, value_parser, default_value_t = 10)]
+    ipf: u64,
+
+    /// Instruction set the ROM was written for
+    #[clap(long, value_enum, default_value_t = VariantArg::Chip8)]
+    variant: VariantArg,
+
+    /// Interpreter to mimic the behaviour of [default: matches the variant]
+    #[clap(long, value_enum)]
+    quirks: Option<QuirksPreset>,
+
+    /// Reset VF after 8XY1, 8XY2 and 8XY3
+    #[clap(long, value_parser)]
+    vf_reset: Option<bool>,
+
+    /// Increment I after FX55 and FX65
+    #[clap(long, value_parser)]
+    memory_increment: Option<bool>,
+
+    /// Shift VX in place with 8XY6 and 8XYE, ignoring VY
+    #[clap(long, value_parser)]
+    shift_in_place: Option<bool>,
+
+    /// Jump to XNN plus VX with BXNN
+    #[clap(long, value_parser)]
+    jump_with_vx: Option<bool>,
+
+    /// Wrap sprites around the edges of the screen instead of clipping them
+    #[clap(long, value_parser)]
+    sprite_wrap: Option<bool>,
+
+    /// Wait for the next frame before drawing a sprite
+    #[clap(long, value_parser)]
+    display_wait: Option<bool>,
+
+    /// Nested subroutine calls allowed [default: 12 with the VIP quirks, otherwise 16]
+    #[clap(long, value_parser)]
+    stack_depth: Option<u8>,
+
+    /// How long instructions take [default: vip with the VIP quirks, otherwise instructions]
+    #[clap(long, value_enum)]
+    timing: Option<TimingArg>,
+
+    /// Seed for the random number generator, to make runs repeatable [default: random]
+    #[clap(long, value_parser)]
+    seed: Option<u64>,
+
+    /// Seconds of play that can be rewound by holding Backspace
+    #[clap(long, value_parser, default_value_t = 10)]
+    rewind_seconds: usize,
+
+    /// Start paused in the debugger, which F12 also stops the program in
+    #[clap(long, value_parser)]
+    debug: bool,
+
+    /// Enter the debugger before the instruction at this address is executed
+    #[clap(long = "break", value_parser = parse_address)]
+    breakpoints: Vec<u16>,
+
+    /// Wait for a debugger to connect with the GDB remote protocol on this local port
+    #[clap(long, value_parser)]
+    gdb_port: Option<u16>,
+
+    /// Write the state every instruction is executed from to this file, one per line
+    #[clap(long, value_parser)]
+    trace: Option<PathBuf>,
+
+    /// Only trace instructions at addresses in this range, such as 0x200-0x2FF
+    #[clap(long, value_parser = parse_address_range, requires = "trace")]
+    trace_range: Option<RangeInclusive<u16>>,
+
+    /// Only write the last N instructions, once the program exits or crashes
+    #[clap(long, value_parser, value_name = "N", requires = "trace")]
+    trace_last: Option<usize>,
+
+    /// Print the instructions, operations and subroutines that took the most cycles at exit
+    #[clap(long, value_parser)]
+    profile: bool,
+
+    /// Write the cycles spent in each stack of subroutines at exit, for flame graph tools
+    #[clap(long, value_parser)]
+    profile_stacks: Option<PathBuf>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Print a listing of the instructions in a ROM
+    Disasm {
+        rom: String,
+
+        /// Assembly language to print instructions in
+        #[clap(long, value_enum, default_value_t = SyntaxArg::Cowgod)]
+        syntax: SyntaxArg,
+
+        /// Address the ROM is loaded at
+        #[clap(long, value_parser = parse_address, default_value_t = PROGRAM_ADDRESS)]
+        base: u16,
+
+        /// Only decode instructions reachable from the start, listing the rest as data
+        #[clap(long, value_parser)]
+        flow: bool,
+    },
+
+    /// Print the control-flow graph of a ROM in the Graphviz DOT language
+    Cfg {
+        rom: String,
+
+        /// Address the ROM is loaded at
+        #[clap(long, value_parser = parse_address, default_value_t = PROGRAM_ADDRESS)]
+        base: u16,
+    },
+
+    /// Assemble a program written with Cowgod's mnemonics into a ROM
+    Asm {
+        source: String,
+
+        /// ROM to write [default: the source with a .ch8 extension]
+        #[clap(short, long, value_parser)]
+        output: Option<PathBuf>,
+    },
+
+    /// Find the first instruction where two traces written with --trace disagree
+    TraceDiff {
+        a: PathBuf,
+        b: PathBuf,
+
+        /// Instructions to show before and after the one where the traces diverge
+        #[clap(long, value_parser, default_value_t = 5)]
+        context: usize,
+    },
+}
+
+#[derive(Clone, ValueEnum)]
+enum SyntaxArg {
+    Cowgod,
+    Octo,
+}
+
+impl From<&SyntaxArg> for Syntax {
+    fn from(syntax: &SyntaxArg) -> Self {
+        match syntax {
+            SyntaxArg::Cowgod => Syntax::Cowgod,
+            SyntaxArg::Octo => Syntax::Octo,
+        }
+    }
+}
+
+/// Parses an address in decimal, or hexadecimal with a 0x prefix.
+fn parse_address(value: &str) -> Result<u16, String> {
+    match value.strip_prefix("0x") {
+        Some(hex) => u16::from_str_radix(hex, 16),
+        None => value.parse(),
+    }
+    .map_err(|e| e.to_string())
+}
+
+/// Parses two addresses separated by a dash, both included in the range.
+fn parse_address_range(value: &str) -> Result<RangeInclusive<u16>, String> {
+    let (start, end) = value
+        .split_once('-')
+        .ok_or("expected a range like 0x200-0x2FF")?;
+    let (start, end) = (parse_address(start)?, parse_address(end)?);
+    if start > end {
+        return Err(format!("{:#05X} comes after {:#05X}", start, end));
+    }
+    Ok(start..=end)
+}
+
+#[derive(Clone, ValueEnum)]
+enum VariantArg {
+    Chip8,
+    Schip,
+    Xochip,
+}
+
+#[derive(Clone, ValueEnum)]
+enum TimingArg {
+    /// Every instruction takes the same time, set by --ipf
+    Instructions,
+    /// Instructions take as long as on the COSMAC VIP
+    Vip,
+}
+
+#[derive(Clone, ValueEnum)]
+enum QuirksPreset {
+    Vip,
+    Chip48,
+    Schip,
+    Xochip,
+}
+
+fn disasm(rom: &str, syntax: Syntax, base: u16, flow: bool) -> Result<(), String> {
+    let data = fs::read(rom).map_err(|e| e.to_string())?;
+    let lines = if flow {
+        Analysis::new(&data, base).disassemble(&data)
+    } else {
+        disassemble(&data, base)
+    };
+
+    for line in lines {
+        println!("{}", line.display(syntax));
+    }
+    Ok(())
+}
+
+fn cfg(rom: &str, base: u16) -> Result<(), String> {
+    let data = fs::read(rom).map_err(|e| e.to_string())?;
+
+    print!("{}", Analysis::new(&data, base).dot());
+    Ok(())
+}
+
+fn asm(source: &str, output: Option<PathBuf>) -> Result<(), String> {
+    let text = fs::read_to_string(source).map_err(|e| e.to_string())?;
+    let rom = assemble(&text).map_err(|e| format!("{}: {}", source, e))?;
+    let output = output.unwrap_or_else(|| Path::new(source).with_extension("ch8"));
+
+    fs::write(&output, &rom).map_err(|e| e.to_string())?;
+    println!("Wrote {} bytes to {}", rom.len(), output.display());
+    Ok(())
+}
+
+fn main() -> Result<(), String> {
+    let args = Args::parse();
+
+    match &args.command {
+        Some(Command::Disasm {
+            rom,
+            syntax,
+            base,
+            flow,
+        }) => return disasm(rom, syntax.into(), *base, *flow),
+        Some(Command::Cfg { rom, base }) => return cfg(rom, *base),
+        Some(Command::Asm { source, output }) => return asm(source, output.clone()),
+        Some(Command::TraceDiff { a, b, context }) => return trace_diff(a, b, *context),
+        None => {}
+    }
+
+    let rom = args.rom.as_deref().ok_or("no ROM given")?;
+    #[cfg(feature = "sdl")]
+    return window::run(&args, Path::new(rom));
+    #[cfg(not(feature = "sdl"))]
+    Err(format!("{}: running ROMs needs the sdl feature", rom))
+}
+
+fn read_trace(path: &Path) -> Result<(String, Vec<TraceRecord>), String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let records = parse_trace(&text).map_err(|e| format!("{}: {}", path.display(), e))?;
+    Ok((text, records))
+}
+
+fn trace_diff(a: &Path, b: &Path, context: usize) -> Result<(), String> {
+    let traces = [read_trace(a)?, read_trace(b)?];
+    let (records_a, records_b) = (&traces[0].1, &traces[1].1);
+
+    let Some(position) = first_divergence(records_a, records_b) else {
+        println!("The traces agree for all {} instructions", records_a.len());
+        return Ok(());
+    };
+
+    print!("The traces diverge at instruction {}", position);
+    // The state in an entry is from before its instruction, so the one before made it.
+    let previous = position.checked_sub(1).map(|n| &records_a[n]);
+    match previous.and_then(|record| Some((record.pc, decode(record.opcode?).ok()?))) {
+        Some((pc, operation)) => println!(", after {:#05X}: {}", pc, operation),
+        None => println!(),
+    }
+    match (records_a.get(position), records_b.get(position)) {
+        (Some(record_a), Some(record_b)) => {
+            for difference in record_a.differences(record_b) {
+                println!("  {}", difference);
+            }
+        }
+        (_, None) => println!("  {} ends first", b.display()),
+        (None, _) => println!("  {} ends first", a.display()),
+    }
+
+    for (path, (text, records)) in [a, b].iter().zip(&traces) {
+        println!("\n{}:", path.display());
+        let lines: Vec<_> = text.lines().collect();
+        let start = position.saturating_sub(context);
+        for (n, record) in records
+            .iter()
+            .enumerate()
+            .take((position + 1).saturating_add(context))
+            .skip(start)
+        {
+            let marker = if n == position { "=>" } else { "  " };
+            println!("{} {:>6}  {}", marker, record.line, lines[record.line - 1]);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,302 @@
+use chip8::config::{Config, Timing, Variant};
+use chip8::constants::{STACK_DEPTH, TIMER_FREQUENCY, VIP_STACK_DEPTH};
+use chip8::debugger::{write_watch_hits, Action, Debugger};
+use chip8::gdb::{GdbStub, Resume, StopReason};
+use chip8::profile::Profiler;
+use chip8::quirks::Quirks;
+use chip8::sdl::{Hotkey, SdlInputOutput};
+use chip8::system::{StepOutcome, System};
+use chip8::timing::{VIP_CYCLES_PER_FRAME, VIP_INTERRUPT_CYCLES};
+use chip8::trace::TraceWriter;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+use super::{Args, QuirksPreset, TimingArg, VariantArg};
+
+impl From<&VariantArg> for Variant {
+    fn from(variant: &VariantArg) -> Self {
+        match variant {
+            VariantArg::Chip8 => Variant::Chip8,
+            VariantArg::Schip => Variant::SuperChip,
+            VariantArg::Xochip => Variant::XoChip,
+        }
+    }
+}
+
+impl Args {
+    fn quirks(&self) -> Quirks {
+        let mut quirks = match self.quirks {
+            None => match self.variant {
+                VariantArg::Chip8 => Quirks::default(),
+                VariantArg::Schip => Quirks::schip(),
+                VariantArg::Xochip => Quirks::xochip(),
+            },
+            Some(QuirksPreset::Vip) => Quirks::vip(),
+            Some(QuirksPreset::Chip48) => Quirks::chip48(),
+            Some(QuirksPreset::Schip) => Quirks::schip(),
+            Some(QuirksPreset::Xochip) => Quirks::xochip(),
+        };
+
+        let overrides = [
+            (self.vf_reset, &mut quirks.vf_reset),
+            (self.memory_increment, &mut quirks.memory_increment),
+            (self.shift_in_place, &mut quirks.shift_in_place),
+            (self.jump_with_vx, &mut quirks.jump_with_vx),
+            (self.sprite_wrap, &mut quirks.sprite_wrap),
+            (self.display_wait, &mut quirks.display_wait),
+        ];
+        for (value, quirk) in overrides {
+            if let Some(value) = value {
+                *quirk = value;
+            }
+        }
+
+        quirks
+    }
+
+    fn timing(&self) -> Timing {
+        match (&self.timing, &self.quirks) {
+            (Some(TimingArg::Instructions), _) => Timing::Instructions,
+            (Some(TimingArg::Vip), _) | (None, Some(QuirksPreset::Vip)) => Timing::CosmacVip,
+            (None, _) => Timing::Instructions,
+        }
+    }
+
+    fn stack_depth(&self) -> u8 {
+        match (self.stack_depth, &self.quirks) {
+            (Some(depth), _) => depth,
+            (None, Some(QuirksPreset::Vip)) => VIP_STACK_DEPTH,
+            (None, _) => STACK_DEPTH,
+        }
+    }
+}
+
+/// Runs `rom` in a window until it exits or the window is closed.
+pub fn run(args: &Args, rom: &Path) -> Result<(), String> {
+    println!("Loading {}", rom.display());
+
+    let config = Config {
+        variant: Variant::from(&args.variant),
+        stack_depth: args.stack_depth(),
+        quirks: args.quirks(),
+        timing: args.timing(),
+        seed: args.seed,
+        ..Config::default()
+    };
+    let mut system =
+        System::with_config(SdlInputOutput::new()?, config).map_err(|e| e.to_string())?;
+    system.set_rewind_capacity(args.rewind_seconds * TIMER_FREQUENCY as usize);
+
+    system.load_rom_from_file(rom).map_err(|e| e.to_string())?;
+    for address in &args.breakpoints {
+        system.add_breakpoint(*address);
+    }
+    if let Some(path) = &args.trace {
+        let file = File::create(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let mut tracer = TraceWriter::new(BufWriter::new(file));
+        if let Some(range) = &args.trace_range {
+            tracer = tracer.with_range(range.clone());
+        }
+        if let Some(limit) = args.trace_last {
+            tracer = tracer.with_limit(limit);
+        }
+        system.set_tracer(Box::new(tracer));
+    }
+    if args.profile || args.profile_stacks.is_some() {
+        system.set_profiler(Profiler::new());
+    }
+    let mut debugger = Debugger::default();
+    let mut gdb = match args.gdb_port {
+        Some(port) => Some(attach_gdb(port).map_err(|e| e.to_string())?),
+        None => None,
+    };
+    let mut paused = args.debug || gdb.is_some();
+    let mut crashed = None;
+
+    let frame = Duration::from_secs(1) / TIMER_FREQUENCY;
+    let mut next_frame = Instant::now() + frame;
+    let budget = match system.config().timing {
+        Timing::Instructions => args.ipf,
+        // The VIP runs as many cycles as fit between two display interrupts.
+        Timing::CosmacVip => VIP_CYCLES_PER_FRAME - VIP_INTERRUPT_CYCLES,
+    };
+
+    loop {
+        if paused {
+            paused = false;
+            let action = match &mut gdb {
+                Some(stub) => match stub.serve(&mut system).map_err(|e| e.to_string())? {
+                    Resume::Continue => Action::Continue,
+                    Resume::Detach => {
+                        gdb = None;
+                        Action::Continue
+                    }
+                    Resume::Kill => Action::Quit,
+                },
+                None => prompt(&mut debugger, &mut system).map_err(|e| e.to_string())?,
+            };
+            if action == Action::Quit {
+                break;
+            }
+            next_frame = Instant::now() + frame;
+        }
+
+        system.poll_input();
+        if system.io().quit_requested() {
+            break;
+        }
+        for hotkey in system.io_mut().take_hotkeys() {
+            paused |= handle_hotkey(&mut system, rom, hotkey);
+        }
+        if let Some(stub) = &mut gdb {
+            paused |= stub.interrupted().map_err(|e| e.to_string())?;
+        }
+        if paused {
+            if let Some(stub) = &mut gdb {
+                stub.stopped(&StopReason::Interrupt)
+                    .map_err(|e| e.to_string())?;
+            }
+            continue;
+        }
+
+        // Frames are stepped back through at the rate they were recorded.
+        let outcome = if system.io().rewinding() {
+            if system.rewind_frame() {
+                system.draw();
+            }
+            Ok(StepOutcome::Executed)
+        } else {
+            system.run_frame(budget)
+        };
+
+        // With a debugger attached, it is told why the program stopped instead.
+        let stop = match outcome {
+            Ok(StepOutcome::Exited) => Some(StopReason::Exited),
+            Ok(StepOutcome::Breakpoint) => Some(StopReason::Breakpoint),
+            Ok(StepOutcome::Watchpoint) => Some(StopReason::Watchpoint(system.watch_hits()[0])),
+            Ok(_) => None,
+            Err(e) if gdb.is_some() => Some(StopReason::Error(e)),
+            Err(e) => {
+                // The profile is still worth having when the program crashes.
+                crashed = Some(e.to_string());
+                break;
+            }
+        };
+        match (stop, &mut gdb) {
+            (None, _) => {}
+            (Some(reason), Some(stub)) => {
+                stub.stopped(&reason).map_err(|e| e.to_string())?;
+                if reason == StopReason::Exited {
+                    break;
+                }
+                paused = true;
+                continue;
+            }
+            (Some(StopReason::Exited), None) => break,
+            (Some(StopReason::Watchpoint(_)), None) => {
+                write_watch_hits(&system, &mut io::stdout()).map_err(|e| e.to_string())?;
+                paused = true;
+                continue;
+            }
+            (Some(_), None) => {
+                println!("Breakpoint at {:#05X}", system.program_counter());
+                paused = true;
+                continue;
+            }
+        }
+
+        // Sleep until the next frame is due, or start over from now after falling behind.
+        let now = Instant::now();
+        if next_frame > now {
+            sleep(next_frame - now);
+            next_frame += frame;
+        } else {
+            next_frame = now + frame;
+        }
+    }
+
+    if let Some(mut tracer) = system.take_tracer() {
+        tracer.finish().map_err(|e| e.to_string())?;
+    }
+    if let Some(profiler) = system.take_profiler() {
+        write_profile(&profiler, args.profile, args.profile_stacks.as_deref())?;
+    }
+    crashed.map_or(Ok(()), Err)
+}
+
+/// Save states live next to the ROM, as `rom.ch8.s1` to `rom.ch8.s9`.
+///
+/// Returns whether the debugger was asked for.
+fn handle_hotkey(system: &mut System<SdlInputOutput>, rom: &Path, hotkey: Hotkey) -> bool {
+    let state_path = |slot: u8| PathBuf::from(format!("{}.s{}", rom.display(), slot));
+
+    let result = match hotkey {
+        Hotkey::SaveState(slot) => system
+            .save_state_to_file(state_path(slot))
+            .map(|_| format!("Saved state to slot {}", slot)),
+        Hotkey::LoadState(slot) => system
+            .load_state_from_file(state_path(slot))
+            .map(|_| format!("Loaded state from slot {}", slot)),
+        Hotkey::Break => return true,
+    };
+
+    match result {
+        Ok(message) => println!("{}", message),
+        Err(e) => eprintln!("{}", e),
+    }
+    false
+}
+
+/// Waits for a debugger to connect on `port` of the loopback interface.
+fn attach_gdb(port: u16) -> io::Result<GdbStub> {
+    let listener = TcpListener::bind(("127.0.0.1", port))?;
+    println!("Waiting for a debugger to connect on port {}", port);
+
+    let (stream, address) = listener.accept()?;
+    println!("Debugger connected from {}", address);
+    GdbStub::new(stream)
+}
+
+/// Reads debugger commands from the terminal until one of them resumes the program.
+fn prompt(debugger: &mut Debugger, system: &mut System<SdlInputOutput>) -> io::Result<Action> {
+    let mut stdout = io::stdout();
+    debugger.show_location(system, &mut stdout)?;
+
+    loop {
+        print!("(chip8) ");
+        stdout.flush()?;
+        let mut line = String::new();
+        if io::stdin().read_line(&mut line)? == 0 {
+            return Ok(Action::Quit);
+        }
+
+        match debugger.execute_line(system, &line, &mut stdout)? {
+            Action::Prompt => {
+                // Show what stepping drew, as the frame loop is not running.
+                if system.draw_flag {
+                    system.draw();
+                    system.draw_flag = false;
+                }
+            }
+            action => return Ok(action),
+        }
+    }
+}
+
+/// How many addresses and subroutines the profile report lists.
+const PROFILE_TOP: usize = 20;
+
+fn write_profile(profiler: &Profiler, report: bool, stacks: Option<&Path>) -> Result<(), String> {
+    if report {
+        print!("{}", profiler.report(PROFILE_TOP));
+    }
+    if let Some(path) = stacks {
+        fs::write(path, profiler.collapsed().to_string())
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+    }
+    Ok(())
+}
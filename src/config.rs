@@ -0,0 +1,148 @@
+use std::ops::Range;
+
+use crate::constants::{
+    BIG_FONT_ADDRESS, BIG_FONT_SET, FONT_ADDRESS, FONT_SET, MEMORY_SIZE, PROGRAM_ADDRESS,
+    STACK_DEPTH, VIP_STACK_DEPTH, XO_CHIP_MEMORY_SIZE,
+};
+use crate::error::ConfigError;
+use crate::quirks::Quirks;
+
+/// Family of interpreters the System emulates, which decides the instructions available.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Variant {
+    /// The original CHIP-8 instruction set.
+    #[default]
+    Chip8,
+
+    /// SUPER-CHIP 1.1, adding a 128x64 display, scrolling and large sprites.
+    SuperChip,
+
+    /// XO-CHIP, extending SUPER-CHIP with bitplanes, audio patterns and 64 KiB of memory.
+    XoChip,
+}
+
+impl Variant {
+    /// Whether the SUPER-CHIP instructions are available.
+    pub fn has_super_chip(&self) -> bool {
+        !matches!(self, Variant::Chip8)
+    }
+
+    /// Whether the XO-CHIP instructions are available.
+    pub fn has_xo_chip(&self) -> bool {
+        matches!(self, Variant::XoChip)
+    }
+
+    /// Bytes of addressable memory.
+    pub fn memory_size(&self) -> usize {
+        match self {
+            Variant::XoChip => XO_CHIP_MEMORY_SIZE,
+            _ => MEMORY_SIZE,
+        }
+    }
+
+    /// Number of RPL user flags FX75 and FX85 can use.
+    pub fn flag_count(&self) -> usize {
+        match self {
+            Variant::Chip8 => 0,
+            Variant::SuperChip => 8,
+            Variant::XoChip => 16,
+        }
+    }
+}
+
+/// How long instructions take to execute.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Timing {
+    /// Every instruction takes one cycle, so speed is set in instructions.
+    #[default]
+    Instructions,
+
+    /// Instructions take as many machine cycles as on the COSMAC VIP.
+    CosmacVip,
+}
+
+/// Machine configuration a System is created with.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Config {
+    /// Interpreter the System emulates.
+    pub variant: Variant,
+
+    /// Address the hexadecimal font is loaded at, inside the reserved area below the load
+    /// address.
+    pub font_address: u16,
+
+    /// Address the SUPER-CHIP large font is loaded at, inside the reserved area below the
+    /// load address.
+    pub big_font_address: u16,
+
+    /// Address ROMs are loaded at, and where execution starts.
+    pub load_address: u16,
+
+    /// Number of nested subroutine calls allowed before the stack overflows.
+    pub stack_depth: u8,
+
+    /// Interpreter behaviours the ROM expects.
+    pub quirks: Quirks,
+
+    /// Cost of instructions, counted by `System::cycles`.
+    pub timing: Timing,
+
+    /// Seed for the random numbers CXNN produces, or None to seed from the clock.
+    pub seed: Option<u64>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            variant: Variant::default(),
+            font_address: FONT_ADDRESS,
+            big_font_address: BIG_FONT_ADDRESS,
+            load_address: PROGRAM_ADDRESS,
+            stack_depth: STACK_DEPTH,
+            quirks: Quirks::default(),
+            timing: Timing::default(),
+            seed: None,
+        }
+    }
+}
+
+impl Config {
+    /// The original COSMAC VIP interpreter, with its behaviours, shallower stack and timing.
+    pub fn vip() -> Self {
+        Config {
+            stack_depth: VIP_STACK_DEPTH,
+            quirks: Quirks::vip(),
+            timing: Timing::CosmacVip,
+            ..Config::default()
+        }
+    }
+
+    /// Checks both fonts fit below the load address without overlapping.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let font = self.font_range(self.font_address, FONT_SET.len())?;
+        let big_font = self.font_range(self.big_font_address, BIG_FONT_SET.len())?;
+
+        if font.start < big_font.end && big_font.start < font.end {
+            return Err(ConfigError::FontsOverlap {
+                font_address: self.font_address,
+                big_font_address: self.big_font_address,
+            });
+        }
+        Ok(())
+    }
+
+    fn font_range(&self, address: u16, size: usize) -> Result<Range<usize>, ConfigError> {
+        let start = address as usize;
+        if start + size > self.variant.memory_size() {
+            return Err(ConfigError::FontOutOfBounds { address, size });
+        }
+        // ROMs are loaded over anything from the load address on.
+        if start + size > self.load_address as usize {
+            return Err(ConfigError::FontOverlapsProgram {
+                address,
+                load_address: self.load_address,
+            });
+        }
+        Ok(start..start + size)
+    }
+}
@@ -1,5 +1,59 @@
-/// Test
-const FONT_SET: [u8; 80] = [
+/// Width of the display in pixels.
+pub const DISPLAY_WIDTH: usize = 64;
+
+/// Height of the display in pixels.
+pub const DISPLAY_HEIGHT: usize = 32;
+
+/// Width of the SUPER-CHIP high resolution display in pixels.
+pub const HIRES_DISPLAY_WIDTH: usize = 128;
+
+/// Height of the SUPER-CHIP high resolution display in pixels.
+pub const HIRES_DISPLAY_HEIGHT: usize = 64;
+
+/// Bytes of memory on the COSMAC VIP, and on SUPER-CHIP.
+pub const MEMORY_SIZE: usize = 4096;
+
+/// Bytes of memory on XO-CHIP.
+pub const XO_CHIP_MEMORY_SIZE: usize = 65536;
+
+/// Number of RPL user flags XO-CHIP can save registers to. SUPER-CHIP only has the first 8.
+pub const FLAG_COUNT: usize = 16;
+
+/// Number of bytes in the XO-CHIP audio pattern buffer.
+pub const AUDIO_PATTERN_SIZE: usize = 16;
+
+/// Pitch the XO-CHIP audio pattern plays at until FX3A changes it, which is 4000 bits a second.
+pub const DEFAULT_PITCH: u8 = 64;
+
+/// Number of nested subroutine calls most interpreters allow.
+pub const STACK_DEPTH: u8 = 16;
+
+/// Number of nested subroutine calls the COSMAC VIP interpreter allows.
+pub const VIP_STACK_DEPTH: u8 = 12;
+
+/// Number of keys on the hexadecimal keypad.
+pub const KEY_COUNT: usize = 16;
+
+/// Rate at which the delay and sound timers count down, in Hz.
+pub const TIMER_FREQUENCY: u32 = 60;
+
+/// Address programs are loaded at and start executing from.
+pub const PROGRAM_ADDRESS: u16 = 0x200;
+
+/// Address of the first font sprite in memory.
+pub const FONT_ADDRESS: u16 = 0x50;
+
+/// Number of bytes in each font sprite.
+pub const FONT_CHARACTER_SIZE: u16 = 5;
+
+/// Address of the first SUPER-CHIP large font sprite in memory.
+pub const BIG_FONT_ADDRESS: u16 = 0xA0;
+
+/// Number of bytes in each large font sprite.
+pub const BIG_FONT_CHARACTER_SIZE: u16 = 10;
+
+/// Sprites for the hexadecimal digits 0-F, each 4 pixels wide and 5 rows tall.
+pub const FONT_SET: [u8; 80] = [
     0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
     0x20, 0x60, 0x20, 0x20, 0x70, // 1
     0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
@@ -15,5 +69,25 @@ const FONT_SET: [u8; 80] = [
     0xF0, 0x80, 0x80, 0x80, 0xF0, // C
     0xE0, 0x90, 0x90, 0x90, 0xE0, // D
     0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
-    0xF0, 0x80, 0xF0, 0x80, 0x80  // F
+    0xF0, 0x80, 0xF0, 0x80, 0x80, // F
+];
+
+/// SUPER-CHIP sprites for the hexadecimal digits 0-F, each 8 pixels wide and 10 rows tall.
+pub const BIG_FONT_SET: [u8; 160] = [
+    0xFF, 0xFF, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, // 0
+    0x18, 0x78, 0x78, 0x18, 0x18, 0x18, 0x18, 0x18, 0xFF, 0xFF, // 1
+    0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // 2
+    0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 3
+    0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0x03, 0x03, // 4
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 5
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 6
+    0xFF, 0xFF, 0x03, 0x03, 0x06, 0x0C, 0x18, 0x18, 0x18, 0x18, // 7
+    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 8
+    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 9
+    0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, // A
+    0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, // B
+    0x3C, 0xFF, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0xFF, 0x3C, // C
+    0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, // D
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // E
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0, // F
 ];
@@ -0,0 +1,594 @@
+use std::io::{self, Write};
+use std::str::FromStr;
+
+use crate::disassembler::{disassemble, Syntax};
+use crate::error::CommandError;
+use crate::input_output::InputOutput;
+use crate::system::{StepOutcome, System};
+use crate::watchpoint::{self, Access, Watchpoint};
+
+/// Bytes shown by `memory` when no length is given.
+const DEFAULT_DUMP_LENGTH: u16 = 64;
+
+/// Instructions shown by `list`, and how many of them come before the address listed.
+const LIST_LENGTH: usize = 8;
+const LIST_CONTEXT: u16 = 3;
+
+const HELP: &str = "\
+step [N]           s  execute N instructions, 1 by default
+continue           c  run until a breakpoint is reached
+break ADDR         b  stop before the instruction at ADDR
+delete ADDR        d  remove the breakpoint at ADDR
+breakpoints           list the breakpoints
+watch ADDR [LEN]   w  stop after LEN bytes from ADDR are written, 1 by default
+rwatch ADDR [LEN]     stop after LEN bytes from ADDR are read
+awatch ADDR [LEN]     stop after LEN bytes from ADDR are read or written
+watch REG          w  stop after V0-VF or I changes
+unwatch N             remove watchpoint number N
+watchpoints           list the watchpoints
+registers          r  print V0-VF, I, PC, SP, the timers and the stack
+memory ADDR [LEN]  x  dump LEN bytes of memory from ADDR, 64 by default
+poke ADDR BYTE...     write bytes to memory from ADDR
+set REG VALUE         set V0-VF, I or PC
+list [ADDR]        l  disassemble around ADDR, the PC by default
+help               h  print this help
+quit               q  stop the emulator
+
+Numbers are decimal, or hexadecimal with a 0x prefix.
+An empty line repeats the last command.
+";
+
+/// A register the debugger can change.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Register {
+    V(u8),
+    I,
+    Pc,
+}
+
+impl FromStr for Register {
+    type Err = CommandError;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        let lower = name.to_ascii_lowercase();
+        match lower.as_str() {
+            "i" => Ok(Register::I),
+            "pc" => Ok(Register::Pc),
+            _ => lower
+                .strip_prefix('v')
+                .filter(|x| x.len() == 1)
+                .and_then(|x| u8::from_str_radix(x, 16).ok())
+                .map(Register::V)
+                .ok_or_else(|| CommandError::InvalidRegister(name.to_string())),
+        }
+    }
+}
+
+/// A debugger command, as typed at the prompt.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    Step(u64),
+    Continue,
+    Break(u16),
+    Delete(u16),
+    Breakpoints,
+    Watch(Watchpoint),
+    Unwatch(usize),
+    Watchpoints,
+    Registers,
+    Memory { address: u16, len: u16 },
+    Poke { address: u16, bytes: Vec<u8> },
+    Set { register: Register, value: u16 },
+    List(Option<u16>),
+    Help,
+    Quit,
+}
+
+impl FromStr for Command {
+    type Err = CommandError;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let mut words = line.split_whitespace();
+        let name = words.next().unwrap_or_default();
+        let mut arguments: Vec<_> = words.collect();
+        arguments.reverse();
+
+        let mut next = |argument| {
+            arguments
+                .pop()
+                .ok_or(CommandError::MissingArgument(argument))
+        };
+        let command = match name {
+            "step" | "s" => Command::Step(optional(next("count").ok())?.unwrap_or(1)),
+            "continue" | "c" => Command::Continue,
+            "break" | "b" => Command::Break(number(next("address")?)?),
+            "delete" | "d" => Command::Delete(number(next("address")?)?),
+            "breakpoints" => Command::Breakpoints,
+            "watch" | "w" | "rwatch" | "awatch" => {
+                let target = next("address or register")?;
+                let watched = match target.parse() {
+                    Ok(Register::V(x)) => Some(watchpoint::Register::V(x)),
+                    Ok(Register::I) => Some(watchpoint::Register::I),
+                    _ => None,
+                };
+
+                match watched {
+                    Some(register) if name == "watch" || name == "w" => {
+                        Command::Watch(Watchpoint::Register(register))
+                    }
+                    _ => {
+                        let start: u16 = number(target)?;
+                        let len = next("length").ok();
+                        let end = optional::<u16>(len)?
+                            .unwrap_or(1)
+                            .checked_sub(1)
+                            .and_then(|len| start.checked_add(len))
+                            .ok_or_else(|| {
+                                CommandError::InvalidNumber(len.unwrap_or_default().to_string())
+                            })?;
+                        let access = match name {
+                            "rwatch" => Access::Read,
+                            "awatch" => Access::ReadWrite,
+                            _ => Access::Write,
+                        };
+                        Command::Watch(Watchpoint::Memory {
+                            range: start..=end,
+                            access,
+                        })
+                    }
+                }
+            }
+            "unwatch" => Command::Unwatch(number(next("number")?)?),
+            "watchpoints" => Command::Watchpoints,
+            "registers" | "r" => Command::Registers,
+            "memory" | "x" => Command::Memory {
+                address: number(next("address")?)?,
+                len: optional(next("length").ok())?.unwrap_or(DEFAULT_DUMP_LENGTH),
+            },
+            "poke" => {
+                let address = number(next("address")?)?;
+                let mut bytes = vec![number(next("byte")?)?];
+                while let Ok(byte) = next("byte") {
+                    bytes.push(number(byte)?);
+                }
+                Command::Poke { address, bytes }
+            }
+            "set" => Command::Set {
+                register: next("register")?.parse()?,
+                value: number(next("value")?)?,
+            },
+            "list" | "l" => Command::List(optional(next("address").ok())?),
+            "help" | "h" | "?" => Command::Help,
+            "quit" | "q" => Command::Quit,
+            _ => return Err(CommandError::UnknownCommand(name.to_string())),
+        };
+
+        if !arguments.is_empty() {
+            return Err(CommandError::TooManyArguments);
+        }
+        Ok(command)
+    }
+}
+
+/// Parses a number in decimal, or hexadecimal with a 0x prefix.
+fn number<T: TryFrom<u64>>(value: &str) -> Result<T, CommandError> {
+    match value.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => value.parse(),
+    }
+    .ok()
+    .and_then(|number| T::try_from(number).ok())
+    .ok_or_else(|| CommandError::InvalidNumber(value.to_string()))
+}
+
+fn optional<T: TryFrom<u64>>(value: Option<&str>) -> Result<Option<T>, CommandError> {
+    value.map(number).transpose()
+}
+
+/// What the front end should do once a command has been executed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    /// Read another command.
+    Prompt,
+
+    /// Let the program run until it reaches a breakpoint.
+    Continue,
+
+    /// Stop the emulator.
+    Quit,
+}
+
+/// Interactive monitor for inspecting and controlling a System while it is stopped.
+///
+/// The debugger does not read input itself: the front end feeds it lines and runs the
+/// System when asked to continue, with the breakpoints set on the System.
+#[derive(Debug, Default)]
+pub struct Debugger {
+    last: Option<Command>,
+}
+
+impl Debugger {
+    /// Parses and executes a line typed at the prompt, writing any output to `out`.
+    ///
+    /// Errors in the command are reported to `out` too. An empty line repeats the last
+    /// command.
+    pub fn execute_line<IO: InputOutput>(
+        &mut self,
+        system: &mut System<IO>,
+        line: &str,
+        out: &mut impl Write,
+    ) -> io::Result<Action> {
+        let command = match line.trim() {
+            "" => match self.last.clone() {
+                Some(command) => command,
+                None => return Ok(Action::Prompt),
+            },
+            line => match line.parse() {
+                Ok(command) => command,
+                Err(e) => {
+                    writeln!(out, "{}", e)?;
+                    return Ok(Action::Prompt);
+                }
+            },
+        };
+
+        let action = self.execute(system, &command, out)?;
+        self.last = Some(command);
+        Ok(action)
+    }
+
+    pub fn execute<IO: InputOutput>(
+        &mut self,
+        system: &mut System<IO>,
+        command: &Command,
+        out: &mut impl Write,
+    ) -> io::Result<Action> {
+        match command {
+            Command::Step(count) => {
+                step(system, *count, out)?;
+                write_listing(system, system.program_counter(), 1, out)?;
+            }
+            Command::Continue => return Ok(Action::Continue),
+            Command::Break(address) => {
+                system.add_breakpoint(*address);
+                writeln!(out, "Breakpoint at {:#05X}", address)?;
+            }
+            Command::Delete(address) => {
+                if !system.remove_breakpoint(*address) {
+                    writeln!(out, "No breakpoint at {:#05X}", address)?;
+                }
+            }
+            Command::Breakpoints => {
+                for address in system.breakpoints() {
+                    writeln!(out, "{:#05X}", address)?;
+                }
+            }
+            Command::Watch(watchpoint) => {
+                writeln!(out, "Watchpoint on {}", watchpoint)?;
+                system.add_watchpoint(watchpoint.clone());
+            }
+            Command::Unwatch(n) => {
+                let watchpoint = n
+                    .checked_sub(1)
+                    .and_then(|i| system.watchpoints().get(i))
+                    .cloned();
+                match watchpoint {
+                    Some(watchpoint) => {
+                        system.remove_watchpoint(&watchpoint);
+                    }
+                    None => writeln!(out, "No watchpoint {}", n)?,
+                }
+            }
+            Command::Watchpoints => {
+                for (i, watchpoint) in system.watchpoints().iter().enumerate() {
+                    writeln!(out, "{}  {}", i + 1, watchpoint)?;
+                }
+            }
+            Command::Registers => write_registers(system, out)?,
+            Command::Memory { address, len } => write_memory(system, *address, *len, out)?,
+            Command::Poke { address, bytes } => {
+                let start = *address as usize;
+                match system.memory_mut().get_mut(start..start + bytes.len()) {
+                    Some(memory) => memory.copy_from_slice(bytes),
+                    None => writeln!(out, "Memory ends at {:#05X}", system.memory().len() - 1)?,
+                }
+            }
+            Command::Set { register, value } => match register {
+                Register::V(x) => match u8::try_from(*value) {
+                    Ok(value) => system.registers_mut()[*x as usize] = value,
+                    Err(_) => writeln!(out, "V{:X} only holds a byte", x)?,
+                },
+                Register::I => system.set_index(*value),
+                Register::Pc => system.set_program_counter(*value),
+            },
+            Command::List(address) => {
+                let address = address.unwrap_or_else(|| system.program_counter());
+                let start = address.saturating_sub(LIST_CONTEXT * 2);
+                write_listing(system, start, LIST_LENGTH, out)?;
+            }
+            Command::Help => write!(out, "{}", HELP)?,
+            Command::Quit => return Ok(Action::Quit),
+        }
+
+        Ok(Action::Prompt)
+    }
+
+    /// Shows where the System is stopped, for when the debugger is entered.
+    pub fn show_location<IO: InputOutput>(
+        &self,
+        system: &System<IO>,
+        out: &mut impl Write,
+    ) -> io::Result<()> {
+        write_listing(system, system.program_counter(), 1, out)
+    }
+}
+
+/// Executes up to `count` instructions, stopping early at breakpoints, watchpoints and
+/// anything that keeps the program from carrying on.
+fn step<IO: InputOutput>(
+    system: &mut System<IO>,
+    count: u64,
+    out: &mut impl Write,
+) -> io::Result<()> {
+    for _ in 0..count {
+        match system.step() {
+            Ok(StepOutcome::Executed) => {}
+            Ok(StepOutcome::Waiting) => return writeln!(out, "Waiting for a key or the display"),
+            Ok(StepOutcome::Exited) => return writeln!(out, "Program exited"),
+            Ok(StepOutcome::Breakpoint | StepOutcome::Watchpoint) => {
+                unreachable!("single steps do not stop")
+            }
+            Err(e) => return writeln!(out, "{}", e),
+        }
+
+        if !system.watch_hits().is_empty() {
+            return write_watch_hits(system, out);
+        }
+        let address = system.program_counter();
+        if system.breakpoints().any(|breakpoint| breakpoint == address) {
+            return writeln!(out, "Breakpoint at {:#05X}", address);
+        }
+    }
+    Ok(())
+}
+
+/// Reports what the last instruction did to watched memory and registers.
+pub fn write_watch_hits<IO: InputOutput>(
+    system: &System<IO>,
+    out: &mut impl Write,
+) -> io::Result<()> {
+    for hit in system.watch_hits() {
+        writeln!(out, "Watchpoint {}", hit)?;
+    }
+    Ok(())
+}
+
+fn write_registers<IO: InputOutput>(system: &System<IO>, out: &mut impl Write) -> io::Result<()> {
+    writeln!(
+        out,
+        "PC {:#05X}  I {:#05X}  SP {}  DT {}  ST {}",
+        system.program_counter(),
+        system.index(),
+        system.stack_pointer(),
+        system.delay_timer(),
+        system.sound_timer()
+    )?;
+
+    for (half, values) in system.registers().chunks(8).enumerate() {
+        let values: Vec<_> = values
+            .iter()
+            .enumerate()
+            .map(|(x, value)| format!("V{:X} {:02X}", half * 8 + x, value))
+            .collect();
+        writeln!(out, "{}", values.join("  "))?;
+    }
+
+    let stack: Vec<_> = system
+        .stack()
+        .iter()
+        .map(|address| format!("{:#05X}", address))
+        .collect();
+    match stack.is_empty() {
+        true => writeln!(out, "Stack empty"),
+        false => writeln!(out, "Stack {}", stack.join(" ")),
+    }
+}
+
+fn write_memory<IO: InputOutput>(
+    system: &System<IO>,
+    address: u16,
+    len: u16,
+    out: &mut impl Write,
+) -> io::Result<()> {
+    let memory = system.memory();
+    let start = (address as usize).min(memory.len());
+    let end = (start + len as usize).min(memory.len());
+
+    for (row, bytes) in memory[start..end].chunks(16).enumerate() {
+        let bytes: Vec<_> = bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+        writeln!(out, "{:#05X}  {}", start + row * 16, bytes.join(" "))?;
+    }
+    Ok(())
+}
+
+/// Disassembles `count` instructions from `start`, marking the PC and breakpoints.
+fn write_listing<IO: InputOutput>(
+    system: &System<IO>,
+    start: u16,
+    count: usize,
+    out: &mut impl Write,
+) -> io::Result<()> {
+    let memory = system.memory();
+    let start = (start as usize).min(memory.len());
+    // F000 is the longest instruction, at four bytes.
+    let end = (start + count * 4).min(memory.len());
+    let breakpoints: Vec<_> = system.breakpoints().collect();
+
+    for line in disassemble(&memory[start..end], start as u16)
+        .iter()
+        .take(count)
+    {
+        let marker = if line.address == system.program_counter() {
+            "=>"
+        } else if breakpoints.contains(&line.address) {
+            " *"
+        } else {
+            "  "
+        };
+        writeln!(out, "{} {}", marker, line.display(Syntax::Cowgod))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::*;
+
+    use super::*;
+
+    /// Runs `lines` through a debugger attached to a System loaded with `program`,
+    /// returning the System and everything the debugger printed.
+    fn debug(program: &[u8], lines: &[&str]) -> (System, String) {
+        let mut system = System::default();
+        system.load_rom(program).unwrap();
+        let mut debugger = Debugger::default();
+        let mut out = Vec::new();
+
+        for line in lines {
+            debugger.execute_line(&mut system, line, &mut out).unwrap();
+        }
+        (system, String::from_utf8(out).unwrap())
+    }
+
+    #[rstest]
+    #[case("s", Command::Step(1))]
+    #[case("step 0x10", Command::Step(16))]
+    #[case("b 0x204", Command::Break(0x204))]
+    #[case("x 0x300 8", Command::Memory { address: 0x300, len: 8 })]
+    #[case("poke 0x300 1 0xFF", Command::Poke { address: 0x300, bytes: vec![1, 0xFF] })]
+    #[case("set vA 0x2A", Command::Set { register: Register::V(0xA), value: 0x2A })]
+    #[case("set PC 512", Command::Set { register: Register::Pc, value: 0x200 })]
+    #[case("list", Command::List(None))]
+    #[case(
+        "watch vf",
+        Command::Watch(Watchpoint::Register(watchpoint::Register::V(0xF)))
+    )]
+    #[case(
+        "awatch 0x300 4",
+        Command::Watch(Watchpoint::Memory { range: 0x300..=0x303, access: Access::ReadWrite })
+    )]
+    fn test_parse(#[case] line: &str, #[case] expected: Command) {
+        assert_eq!(line.parse(), Ok(expected));
+    }
+
+    #[rstest]
+    #[case("jump", CommandError::UnknownCommand("jump".to_string()))]
+    #[case("break", CommandError::MissingArgument("address"))]
+    #[case("poke 0x300 256", CommandError::InvalidNumber("256".to_string()))]
+    #[case("set vg 1", CommandError::InvalidRegister("vg".to_string()))]
+    #[case("continue 2", CommandError::TooManyArguments)]
+    #[case("rwatch i", CommandError::InvalidNumber("i".to_string()))]
+    #[case("watch 0x300 0", CommandError::InvalidNumber("0".to_string()))]
+    fn test_parse_errors(#[case] line: &str, #[case] expected: CommandError) {
+        assert_eq!(line.parse::<Command>(), Err(expected));
+    }
+
+    #[test]
+    fn test_step_and_registers() {
+        let (system, out) = debug(
+            &[0x60, 0x2A, 0xA3, 0x00, 0x22, 0x08, 0x00, 0x00, 0x00, 0xEE],
+            &["step", "", "s", "r"],
+        );
+
+        assert_eq!(system.program_counter(), 0x208);
+        assert_eq!(
+            out,
+            "=> 0x202  A3 00        LD I, 0x300\n\
+             => 0x204  22 08        CALL 0x208\n\
+             => 0x208  00 EE        RET\n\
+             PC 0x208  I 0x300  SP 1  DT 0  ST 0\n\
+             V0 2A  V1 00  V2 00  V3 00  V4 00  V5 00  V6 00  V7 00\n\
+             V8 00  V9 00  VA 00  VB 00  VC 00  VD 00  VE 00  VF 00\n\
+             Stack 0x206\n"
+        );
+    }
+
+    #[test]
+    fn test_steps_stop_at_breakpoints() {
+        let (system, out) = debug(&[0x60, 0x01, 0x61, 0x02, 0x12, 0x00], &["b 0x202", "s 5"]);
+
+        assert_eq!(system.program_counter(), 0x202);
+        assert!(out.ends_with("Breakpoint at 0x202\n=> 0x202  61 02        LD V1, 0x02\n"));
+    }
+
+    #[test]
+    fn test_memory_and_poke() {
+        let (system, out) = debug(
+            &[0x00, 0xE0],
+            &[
+                "poke 0x300 0xDE 0xAD",
+                "set v3 7",
+                "set i 0x300",
+                "x 0x2FE 4",
+            ],
+        );
+
+        assert_eq!(system.registers()[3], 7);
+        assert_eq!(system.index(), 0x300);
+        assert_eq!(out, "0x2FE  00 00 DE AD\n");
+    }
+
+    #[test]
+    fn test_list_marks_pc_and_breakpoints() {
+        let (_, out) = debug(
+            &[0x60, 0x01, 0x61, 0x02, 0x12, 0x00],
+            &["b 0x204", "s", "l"],
+        );
+
+        assert!(out.ends_with(
+            "   0x1FC  00 00        SYS 0x000\n\
+             \x20  0x1FE  00 00        SYS 0x000\n\
+             \x20  0x200  60 01        LD V0, 0x01\n\
+             => 0x202  61 02        LD V1, 0x02\n\
+             \x20* 0x204  12 00        JP 0x200\n\
+             \x20  0x206  00 00        SYS 0x000\n\
+             \x20  0x208  00 00        SYS 0x000\n\
+             \x20  0x20A  00 00        SYS 0x000\n"
+        ));
+    }
+
+    #[test]
+    fn test_steps_stop_at_watchpoints() {
+        let (system, out) = debug(
+            &[
+                0x60, 0x07, // LD V0, 7
+                0xA3, 0x00, // LD I, 0x300
+                0xF0, 0x33, // LD B, V0
+                0x00, 0xE0, // CLS
+            ],
+            &[
+                "watch 0x302",
+                "watch I",
+                "s 10",
+                "s 10",
+                "watchpoints",
+                "unwatch 1",
+                "s",
+            ],
+        );
+
+        assert_eq!(system.program_counter(), 0x208);
+        assert_eq!(
+            out,
+            "Watchpoint on write 0x302\n\
+             Watchpoint on change I\n\
+             Watchpoint 0x202 changed I: 0x000 -> 0x300\n\
+             => 0x204  F0 33        LD B, V0\n\
+             Watchpoint 0x204 wrote 0x302: 0x00 -> 0x07\n\
+             => 0x206  00 E0        CLS\n\
+             1  write 0x302\n\
+             2  change I\n\
+             => 0x208  00 00        SYS 0x000\n"
+        );
+    }
+}
@@ -0,0 +1,299 @@
+use std::fmt;
+
+use crate::opcode::{decode, Operation};
+
+/// Assembly language dialect instructions are written in.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Syntax {
+    /// Mnemonics from Cowgod's Chip-8 Technical Reference, such as `LD V0, 0x2A`.
+    ///
+    /// Source: http://devernay.free.fr/hacks/chip8/C8TECH10.HTM
+    #[default]
+    Cowgod,
+
+    /// Statements of John Earnest's Octo assembler, such as `v0 := 0x2A`.
+    ///
+    /// Source: https://github.com/JohnEarnest/Octo/blob/gh-pages/docs/Manual.md
+    Octo,
+}
+
+/// An Operation written out in one of the assembly syntaxes.
+pub struct Mnemonic<'a> {
+    operation: &'a Operation,
+    syntax: Syntax,
+}
+
+impl Operation {
+    pub fn mnemonic(&self, syntax: Syntax) -> Mnemonic<'_> {
+        Mnemonic {
+            operation: self,
+            syntax,
+        }
+    }
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.mnemonic(Syntax::Cowgod).fmt(f)
+    }
+}
+
+impl fmt::Display for Mnemonic<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.syntax {
+            Syntax::Cowgod => write_cowgod(self.operation, f),
+            Syntax::Octo => write_octo(self.operation, f),
+        }
+    }
+}
+
+fn write_cowgod(operation: &Operation, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match *operation {
+        Operation::NoOperation => write!(f, "SYS 0x000"),
+        Operation::ClearDisplay => write!(f, "CLS"),
+        Operation::SubroutineReturn => write!(f, "RET"),
+        Operation::GotoAddress { nnn } => write!(f, "JP {:#05X}", nnn),
+        Operation::SubroutineCall { nnn } => write!(f, "CALL {:#05X}", nnn),
+        Operation::EqualityCheck { x, nn } => write!(f, "SE V{:X}, {:#04X}", x, nn),
+        Operation::InequalityCheck { x, nn } => write!(f, "SNE V{:X}, {:#04X}", x, nn),
+        Operation::EqualityRegisterCheck { x, y } => write!(f, "SE V{:X}, V{:X}", x, y),
+        Operation::SetRegister { x, nn } => write!(f, "LD V{:X}, {:#04X}", x, nn),
+        Operation::AddRegister { x, nn } => write!(f, "ADD V{:X}, {:#04X}", x, nn),
+        Operation::SetRegisterFromRegister { x, y } => write!(f, "LD V{:X}, V{:X}", x, y),
+        Operation::BitwiseOr { x, y } => write!(f, "OR V{:X}, V{:X}", x, y),
+        Operation::BitwiseAnd { x, y } => write!(f, "AND V{:X}, V{:X}", x, y),
+        Operation::BitwiseXor { x, y } => write!(f, "XOR V{:X}, V{:X}", x, y),
+        Operation::AddValues { x, y } => write!(f, "ADD V{:X}, V{:X}", x, y),
+        Operation::SubtractValues { x, y } => write!(f, "SUB V{:X}, V{:X}", x, y),
+        Operation::StoreLeastSignificant { x, y } => write!(f, "SHR V{:X}, V{:X}", x, y),
+        Operation::SubtractValueFromRegister { x, y } => write!(f, "SUBN V{:X}, V{:X}", x, y),
+        Operation::StoreMostSignificant { x, y } => write!(f, "SHL V{:X}, V{:X}", x, y),
+        Operation::InequalityRegisterCheck { x, y } => write!(f, "SNE V{:X}, V{:X}", x, y),
+        Operation::SetIndexToAddress { nnn } => write!(f, "LD I, {:#05X}", nnn),
+        Operation::GotoAddressWithRegister { nnn } => write!(f, "JP V0, {:#05X}", nnn),
+        Operation::AssignRandomNumber { x, nn } => write!(f, "RND V{:X}, {:#04X}", x, nn),
+        Operation::DrawSprite { x, y, n } => write!(f, "DRW V{:X}, V{:X}, {}", x, y, n),
+        Operation::SkipIfKeyPressed { x } => write!(f, "SKP V{:X}", x),
+        Operation::SkipIfKeyNotPressed { x } => write!(f, "SKNP V{:X}", x),
+        Operation::GetDelayTimer { x } => write!(f, "LD V{:X}, DT", x),
+        Operation::StoreNextKeypress { x } => write!(f, "LD V{:X}, K", x),
+        Operation::SetDelayTimer { x } => write!(f, "LD DT, V{:X}", x),
+        Operation::SetSoundTimer { x } => write!(f, "LD ST, V{:X}", x),
+        Operation::AddToIndex { x } => write!(f, "ADD I, V{:X}", x),
+        Operation::SetIndexToSprite { x } => write!(f, "LD F, V{:X}", x),
+        Operation::StoreBinaryCodedDecimal { x } => write!(f, "LD B, V{:X}", x),
+        Operation::StoreRegistersInMemory { x } => write!(f, "LD [I], V{:X}", x),
+        Operation::SetRegistersFromMemory { x } => write!(f, "LD V{:X}, [I]", x),
+        Operation::ScrollDown { n } => write!(f, "SCD {}", n),
+        Operation::ScrollRight => write!(f, "SCR"),
+        Operation::ScrollLeft => write!(f, "SCL"),
+        Operation::Exit => write!(f, "EXIT"),
+        Operation::LowResolution => write!(f, "LOW"),
+        Operation::HighResolution => write!(f, "HIGH"),
+        Operation::SetIndexToLargeSprite { x } => write!(f, "LD HF, V{:X}", x),
+        Operation::StoreRegistersInFlags { x } => write!(f, "LD R, V{:X}", x),
+        Operation::SetRegistersFromFlags { x } => write!(f, "LD V{:X}, R", x),
+        Operation::ScrollUp { n } => write!(f, "SCU {}", n),
+        Operation::StoreRegisterRange { x, y } => write!(f, "LD [I], V{:X}-V{:X}", x, y),
+        Operation::LoadRegisterRange { x, y } => write!(f, "LD V{:X}-V{:X}, [I]", x, y),
+        Operation::SetIndexToLongAddress => write!(f, "LD I, LONG"),
+        Operation::SelectPlanes { x } => write!(f, "PLANE {}", x),
+        Operation::StoreAudioPattern => write!(f, "AUDIO"),
+        Operation::SetPitch { x } => write!(f, "PITCH V{:X}", x),
+    }
+}
+
+/// Octo spells out skips as the condition under which the next instruction runs,
+/// so the comparisons read the opposite way round to the opcodes.
+fn write_octo(operation: &Operation, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match *operation {
+        Operation::NoOperation => write!(f, "0x00 0x00"),
+        Operation::ClearDisplay => write!(f, "clear"),
+        Operation::SubroutineReturn => write!(f, "return"),
+        Operation::GotoAddress { nnn } => write!(f, "jump {:#05X}", nnn),
+        Operation::SubroutineCall { nnn } => write!(f, ":call {:#05X}", nnn),
+        Operation::EqualityCheck { x, nn } => write!(f, "if v{:x} != {:#04X} then", x, nn),
+        Operation::InequalityCheck { x, nn } => write!(f, "if v{:x} == {:#04X} then", x, nn),
+        Operation::EqualityRegisterCheck { x, y } => write!(f, "if v{:x} != v{:x} then", x, y),
+        Operation::SetRegister { x, nn } => write!(f, "v{:x} := {:#04X}", x, nn),
+        Operation::AddRegister { x, nn } => write!(f, "v{:x} += {:#04X}", x, nn),
+        Operation::SetRegisterFromRegister { x, y } => write!(f, "v{:x} := v{:x}", x, y),
+        Operation::BitwiseOr { x, y } => write!(f, "v{:x} |= v{:x}", x, y),
+        Operation::BitwiseAnd { x, y } => write!(f, "v{:x} &= v{:x}", x, y),
+        Operation::BitwiseXor { x, y } => write!(f, "v{:x} ^= v{:x}", x, y),
+        Operation::AddValues { x, y } => write!(f, "v{:x} += v{:x}", x, y),
+        Operation::SubtractValues { x, y } => write!(f, "v{:x} -= v{:x}", x, y),
+        Operation::StoreLeastSignificant { x, y } => write!(f, "v{:x} >>= v{:x}", x, y),
+        Operation::SubtractValueFromRegister { x, y } => write!(f, "v{:x} =- v{:x}", x, y),
+        Operation::StoreMostSignificant { x, y } => write!(f, "v{:x} <<= v{:x}", x, y),
+        Operation::InequalityRegisterCheck { x, y } => write!(f, "if v{:x} == v{:x} then", x, y),
+        Operation::SetIndexToAddress { nnn } => write!(f, "i := {:#05X}", nnn),
+        Operation::GotoAddressWithRegister { nnn } => write!(f, "jump0 {:#05X}", nnn),
+        Operation::AssignRandomNumber { x, nn } => write!(f, "v{:x} := random {:#04X}", x, nn),
+        Operation::DrawSprite { x, y, n } => write!(f, "sprite v{:x} v{:x} {}", x, y, n),
+        Operation::SkipIfKeyPressed { x } => write!(f, "if v{:x} -key then", x),
+        Operation::SkipIfKeyNotPressed { x } => write!(f, "if v{:x} key then", x),
+        Operation::GetDelayTimer { x } => write!(f, "v{:x} := delay", x),
+        Operation::StoreNextKeypress { x } => write!(f, "v{:x} := key", x),
+        Operation::SetDelayTimer { x } => write!(f, "delay := v{:x}", x),
+        Operation::SetSoundTimer { x } => write!(f, "buzzer := v{:x}", x),
+        Operation::AddToIndex { x } => write!(f, "i += v{:x}", x),
+        Operation::SetIndexToSprite { x } => write!(f, "i := hex v{:x}", x),
+        Operation::StoreBinaryCodedDecimal { x } => write!(f, "bcd v{:x}", x),
+        Operation::StoreRegistersInMemory { x } => write!(f, "save v{:x}", x),
+        Operation::SetRegistersFromMemory { x } => write!(f, "load v{:x}", x),
+        Operation::ScrollDown { n } => write!(f, "scroll-down {}", n),
+        Operation::ScrollRight => write!(f, "scroll-right"),
+        Operation::ScrollLeft => write!(f, "scroll-left"),
+        Operation::Exit => write!(f, "exit"),
+        Operation::LowResolution => write!(f, "lores"),
+        Operation::HighResolution => write!(f, "hires"),
+        Operation::SetIndexToLargeSprite { x } => write!(f, "i := bighex v{:x}", x),
+        Operation::StoreRegistersInFlags { x } => write!(f, "saveflags v{:x}", x),
+        Operation::SetRegistersFromFlags { x } => write!(f, "loadflags v{:x}", x),
+        Operation::ScrollUp { n } => write!(f, "scroll-up {}", n),
+        Operation::StoreRegisterRange { x, y } => write!(f, "save v{:x} - v{:x}", x, y),
+        Operation::LoadRegisterRange { x, y } => write!(f, "load v{:x} - v{:x}", x, y),
+        Operation::SetIndexToLongAddress => write!(f, "i := long"),
+        Operation::SelectPlanes { x } => write!(f, "plane {}", x),
+        Operation::StoreAudioPattern => write!(f, "audio"),
+        Operation::SetPitch { x } => write!(f, "pitch := v{:x}", x),
+    }
+}
+
+/// One line of a disassembly listing: an instruction, or bytes that do not decode as one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Line {
+    pub address: u16,
+
+    /// The instruction's words, or the data bytes.
+    pub bytes: Vec<u8>,
+
+    /// None when the bytes are not a valid instruction.
+    pub operation: Option<Operation>,
+}
+
+impl Line {
+    pub fn display(&self, syntax: Syntax) -> LineDisplay<'_> {
+        LineDisplay { line: self, syntax }
+    }
+}
+
+/// A Line formatted as `address  raw bytes  mnemonic`.
+pub struct LineDisplay<'a> {
+    line: &'a Line,
+    syntax: Syntax,
+}
+
+impl fmt::Display for LineDisplay<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Line {
+            address,
+            bytes,
+            operation,
+        } = self.line;
+        let raw: Vec<_> = bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+
+        write!(f, "{:#05X}  {:<11}  ", address, raw.join(" "))?;
+        match operation {
+            Some(operation) => {
+                write!(f, "{}", operation.mnemonic(self.syntax))?;
+                // The address F000 loads is the word after it.
+                if let [_, _, hi, lo] = bytes[..] {
+                    write!(f, " {:#06X}", u16::from_be_bytes([hi, lo]))?;
+                }
+                Ok(())
+            }
+            None => {
+                let data: Vec<_> = bytes.iter().map(|byte| format!("{:#04X}", byte)).collect();
+                match self.syntax {
+                    Syntax::Cowgod => write!(f, "DB {}", data.join(", ")),
+                    Syntax::Octo => write!(f, "{}", data.join(" ")),
+                }
+            }
+        }
+    }
+}
+
+/// Decodes `bytes` loaded at `base` from start to end, one word at a time.
+///
+/// Words that are not instructions are listed as data. As data and code are not told
+/// apart, sprites and other data that happen to look like instructions are listed as such.
+pub fn disassemble(bytes: &[u8], base: u16) -> Vec<Line> {
+    let mut lines = Vec::new();
+    let mut offset = 0;
+
+    while offset < bytes.len() {
+        let address = base.wrapping_add(offset as u16);
+        let word = bytes
+            .get(offset..offset + 2)
+            .map(|word| u16::from_be_bytes([word[0], word[1]]));
+
+        let (len, operation) = match word.map(decode) {
+            Some(Ok(Operation::SetIndexToLongAddress)) if offset + 4 <= bytes.len() => {
+                (4, Some(Operation::SetIndexToLongAddress))
+            }
+            Some(Ok(Operation::SetIndexToLongAddress)) => (2, None),
+            Some(Ok(operation)) => (2, Some(operation)),
+            Some(Err(_)) => (2, None),
+            None => (1, None),
+        };
+
+        lines.push(Line {
+            address,
+            bytes: bytes[offset..offset + len].to_vec(),
+            operation,
+        });
+        offset += len;
+    }
+
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mnemonics() {
+        let operation = Operation::EqualityCheck { x: 0xA, nn: 0x2A };
+        assert_eq!(operation.to_string(), "SE VA, 0x2A");
+        assert_eq!(
+            operation.mnemonic(Syntax::Octo).to_string(),
+            "if va != 0x2A then"
+        );
+
+        let operation = Operation::DrawSprite { x: 0, y: 1, n: 5 };
+        assert_eq!(operation.to_string(), "DRW V0, V1, 5");
+        assert_eq!(
+            operation.mnemonic(Syntax::Octo).to_string(),
+            "sprite v0 v1 5"
+        );
+    }
+
+    #[test]
+    fn test_disassemble() {
+        let lines = disassemble(
+            &[0xA2, 0x08, 0xF0, 0x00, 0x12, 0x34, 0xFF, 0xFF, 0xF0],
+            0x200,
+        );
+        let listing: Vec<_> = lines
+            .iter()
+            .map(|line| line.display(Syntax::Cowgod).to_string())
+            .collect();
+
+        assert_eq!(
+            listing,
+            [
+                "0x200  A2 08        LD I, 0x208",
+                "0x202  F0 00 12 34  LD I, LONG 0x1234",
+                "0x206  FF FF        DB 0xFF, 0xFF",
+                "0x208  F0           DB 0xF0",
+            ]
+        );
+        assert_eq!(
+            lines[2].display(Syntax::Octo).to_string(),
+            "0x206  FF FF        0xFF 0xFF"
+        );
+    }
+}
@@ -0,0 +1,341 @@
+use std::error::Error;
+use std::{fmt, io};
+
+/// Reasons the System can fail to execute an instruction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExecutionError {
+    /// The word at `address` is not an instruction the System understands.
+    InvalidOpcode { address: u16, word: u16 },
+
+    /// A subroutine call at `address` nested deeper than the stack allows.
+    StackOverflow { address: u16 },
+
+    /// A return at `address` was executed with nothing on the stack.
+    StackUnderflow { address: u16 },
+
+    /// The program counter points outside of memory.
+    ProgramCounterOutOfBounds { address: u16 },
+
+    /// The instruction at `address` accessed memory at `target`, which does not exist.
+    MemoryOutOfBounds { address: u16, target: usize },
+}
+
+impl fmt::Display for ExecutionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExecutionError::InvalidOpcode { address, word } => {
+                write!(f, "invalid opcode {:#06X} at {:#05X}", word, address)
+            }
+            ExecutionError::StackOverflow { address } => {
+                write!(f, "stack overflow at {:#05X}", address)
+            }
+            ExecutionError::StackUnderflow { address } => {
+                write!(f, "stack underflow at {:#05X}", address)
+            }
+            ExecutionError::ProgramCounterOutOfBounds { address } => {
+                write!(f, "program counter out of bounds at {:#05X}", address)
+            }
+            ExecutionError::MemoryOutOfBounds { address, target } => {
+                write!(
+                    f,
+                    "memory access out of bounds at {:#05X} (address {:#X})",
+                    address, target
+                )
+            }
+        }
+    }
+}
+
+impl Error for ExecutionError {}
+
+/// Reasons a ROM can fail to load.
+#[derive(Debug)]
+pub enum RomError {
+    /// The ROM could not be read.
+    Io(io::Error),
+
+    /// The ROM contains no data.
+    Empty,
+
+    /// The ROM is `size` bytes, but only `capacity` bytes fit after the load address.
+    TooLarge { size: usize, capacity: usize },
+}
+
+impl fmt::Display for RomError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RomError::Io(error) => write!(f, "failed to read ROM: {}", error),
+            RomError::Empty => write!(f, "ROM is empty"),
+            RomError::TooLarge { size, capacity } => write!(
+                f,
+                "ROM is {} bytes, but only {} bytes fit in memory",
+                size, capacity
+            ),
+        }
+    }
+}
+
+impl Error for RomError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            RomError::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for RomError {
+    fn from(error: io::Error) -> Self {
+        RomError::Io(error)
+    }
+}
+
+/// Reasons a Config can not be used to create a System.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigError {
+    /// The font of `size` bytes at `address` does not fit in memory.
+    FontOutOfBounds { address: u16, size: usize },
+
+    /// The font at `address` runs into the program loaded at `load_address`.
+    FontOverlapsProgram { address: u16, load_address: u16 },
+
+    /// The small font at `font_address` and the large one at `big_font_address` overlap.
+    FontsOverlap {
+        font_address: u16,
+        big_font_address: u16,
+    },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::FontOutOfBounds { address, size } => write!(
+                f,
+                "font of {} bytes at {:#05X} does not fit in memory",
+                size, address
+            ),
+            ConfigError::FontOverlapsProgram {
+                address,
+                load_address,
+            } => write!(
+                f,
+                "font at {:#05X} runs into the program at {:#05X}",
+                address, load_address
+            ),
+            ConfigError::FontsOverlap {
+                font_address,
+                big_font_address,
+            } => write!(
+                f,
+                "font at {:#05X} overlaps the large font at {:#05X}",
+                font_address, big_font_address
+            ),
+        }
+    }
+}
+
+impl Error for ConfigError {}
+
+/// Reasons a save state can fail to be written or restored.
+#[derive(Debug)]
+pub enum SaveStateError {
+    /// The save state could not be read or written.
+    Io(io::Error),
+
+    /// The data does not start with the save state magic bytes.
+    NotASaveState,
+
+    /// The save state was written in a newer format than this build understands.
+    UnsupportedVersion { version: u16 },
+
+    /// A chunk the machine cannot be restored without is not present.
+    MissingChunk { tag: [u8; 4] },
+
+    /// A chunk is truncated or holds values the machine cannot have.
+    MalformedChunk { tag: [u8; 4] },
+}
+
+impl fmt::Display for SaveStateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SaveStateError::Io(error) => write!(f, "failed to access save state: {}", error),
+            SaveStateError::NotASaveState => write!(f, "not a save state"),
+            SaveStateError::UnsupportedVersion { version } => {
+                write!(f, "save state version {} is not supported", version)
+            }
+            SaveStateError::MissingChunk { tag } => {
+                write!(
+                    f,
+                    "save state has no {:?} chunk",
+                    String::from_utf8_lossy(tag)
+                )
+            }
+            SaveStateError::MalformedChunk { tag } => write!(
+                f,
+                "save state has a malformed {:?} chunk",
+                String::from_utf8_lossy(tag)
+            ),
+        }
+    }
+}
+
+impl Error for SaveStateError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            SaveStateError::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for SaveStateError {
+    fn from(error: io::Error) -> Self {
+        SaveStateError::Io(error)
+    }
+}
+
+/// Why a line of assembly could not be assembled.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AssemblyErrorKind {
+    /// The mnemonic is not an instruction or directive.
+    UnknownMnemonic(String),
+
+    /// The operands do not fit any form of the instruction.
+    InvalidOperands(String),
+
+    /// An operand is not a register, keyword, number or label.
+    InvalidOperand(String),
+
+    /// A number could not be parsed.
+    InvalidNumber(String),
+
+    /// A number does not fit in the field it is used for.
+    OutOfRange { value: u32, max: u32 },
+
+    /// A label is not a valid identifier.
+    InvalidLabel(String),
+
+    /// A label is defined more than once.
+    DuplicateLabel(String),
+
+    /// A label is used, but never defined.
+    UnknownLabel(String),
+}
+
+/// An error in assembly source, at a 1-based line and column.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AssemblyError {
+    pub line: usize,
+    pub column: usize,
+    pub kind: AssemblyErrorKind,
+}
+
+impl fmt::Display for AssemblyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}, column {}: ", self.line, self.column)?;
+        match &self.kind {
+            AssemblyErrorKind::UnknownMnemonic(mnemonic) => {
+                write!(f, "unknown mnemonic {:?}", mnemonic)
+            }
+            AssemblyErrorKind::InvalidOperands(mnemonic) => {
+                write!(f, "invalid operands for {}", mnemonic)
+            }
+            AssemblyErrorKind::InvalidOperand(operand) => {
+                write!(f, "invalid operand {:?}", operand)
+            }
+            AssemblyErrorKind::InvalidNumber(number) => write!(f, "invalid number {:?}", number),
+            AssemblyErrorKind::OutOfRange { value, max } => {
+                write!(f, "{:#X} is larger than the maximum of {:#X}", value, max)
+            }
+            AssemblyErrorKind::InvalidLabel(label) => write!(f, "invalid label {:?}", label),
+            AssemblyErrorKind::DuplicateLabel(label) => {
+                write!(f, "label {:?} is already defined", label)
+            }
+            AssemblyErrorKind::UnknownLabel(label) => write!(f, "unknown label {:?}", label),
+        }
+    }
+}
+
+impl Error for AssemblyError {}
+
+/// A field of an Operation is too large for its place in the opcode.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EncodeError {
+    pub field: &'static str,
+    pub value: u16,
+    pub max: u16,
+}
+
+impl fmt::Display for EncodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} is {:#X}, but can be at most {:#X}",
+            self.field, self.value, self.max
+        )
+    }
+}
+
+impl Error for EncodeError {}
+
+/// Reasons a debugger command can fail to parse.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CommandError {
+    /// No command has this name.
+    UnknownCommand(String),
+
+    /// The command needs an argument that was not given.
+    MissingArgument(&'static str),
+
+    /// An argument is not a number, or does not fit where it is used.
+    InvalidNumber(String),
+
+    /// No register has this name.
+    InvalidRegister(String),
+
+    /// More arguments were given than the command takes.
+    TooManyArguments,
+}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CommandError::UnknownCommand(name) => write!(f, "unknown command {:?}", name),
+            CommandError::MissingArgument(argument) => write!(f, "missing {}", argument),
+            CommandError::InvalidNumber(number) => write!(f, "invalid number {:?}", number),
+            CommandError::InvalidRegister(name) => write!(f, "invalid register {:?}", name),
+            CommandError::TooManyArguments => write!(f, "too many arguments"),
+        }
+    }
+}
+
+impl Error for CommandError {}
+
+/// Why a line of a trace could not be read.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TraceErrorKind {
+    /// A field has a value that is not a number, or does not fit.
+    InvalidField(String),
+
+    /// The line has no program counter.
+    MissingPc,
+}
+
+/// An error reading a trace, at a 1-based line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceError {
+    pub line: usize,
+    pub kind: TraceErrorKind,
+}
+
+impl fmt::Display for TraceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: ", self.line)?;
+        match &self.kind {
+            TraceErrorKind::InvalidField(field) => write!(f, "invalid field {:?}", field),
+            TraceErrorKind::MissingPc => write!(f, "missing pc"),
+        }
+    }
+}
+
+impl Error for TraceError {}
@@ -0,0 +1,147 @@
+use crate::constants::{DISPLAY_HEIGHT, DISPLAY_WIDTH};
+
+/// Pixel buffer the interpreter draws sprites into.
+///
+/// Each pixel is stored as a byte holding one bit per bitplane, so a pixel is lit when
+/// it is non-zero. Only XO-CHIP draws to more than the first plane.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Framebuffer {
+    width: usize,
+    height: usize,
+    pixels: Vec<u8>,
+}
+
+impl Default for Framebuffer {
+    fn default() -> Self {
+        Framebuffer::new(DISPLAY_WIDTH, DISPLAY_HEIGHT)
+    }
+}
+
+impl Framebuffer {
+    pub fn new(width: usize, height: usize) -> Self {
+        Framebuffer {
+            width,
+            height,
+            pixels: vec![0; width * height],
+        }
+    }
+
+    /// Wraps existing pixel data, as long as there is exactly one byte per pixel.
+    pub fn from_pixels(width: usize, height: usize, pixels: Vec<u8>) -> Option<Self> {
+        (pixels.len() == width * height).then_some(Framebuffer {
+            width,
+            height,
+            pixels,
+        })
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Row-major pixel data, `width * height` bytes long.
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> u8 {
+        self.pixels[y * self.width + x]
+    }
+
+    pub fn clear(&mut self) {
+        self.pixels.fill(0);
+    }
+
+    /// Blanks the given bitplanes, leaving the others untouched.
+    pub fn clear_planes(&mut self, planes: u8) {
+        for pixel in self.pixels.iter_mut() {
+            *pixel &= !planes;
+        }
+    }
+
+    /// Moves the given bitplanes by (dx, dy) pixels, blanking whatever is scrolled in.
+    pub fn scroll(&mut self, dx: isize, dy: isize, planes: u8) {
+        let previous = self.pixels.clone();
+
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let (source_x, source_y) = (x as isize - dx, y as isize - dy);
+                let scrolled = if (0..self.width as isize).contains(&source_x)
+                    && (0..self.height as isize).contains(&source_y)
+                {
+                    previous[source_y as usize * self.width + source_x as usize] & planes
+                } else {
+                    0
+                };
+
+                let pixel = &mut self.pixels[y * self.width + x];
+                *pixel = (*pixel & !planes) | scrolled;
+            }
+        }
+    }
+
+    /// XORs the pixel at (x, y) on the given bitplane, returning true if it was lit beforehand.
+    pub fn toggle(&mut self, x: usize, y: usize, plane: u8) -> bool {
+        let pixel = &mut self.pixels[y * self.width + x];
+        let collision = *pixel & plane > 0;
+        *pixel ^= plane;
+        collision
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Framebuffer with a single pixel lit at (x, y) on the first plane.
+    fn with_pixel(x: usize, y: usize) -> Framebuffer {
+        let mut framebuffer = Framebuffer::new(8, 4);
+        framebuffer.toggle(x, y, 1);
+        framebuffer
+    }
+
+    #[test]
+    fn test_scroll_down() {
+        let mut framebuffer = with_pixel(2, 1);
+        framebuffer.scroll(0, 2, 1);
+        assert_eq!(framebuffer, with_pixel(2, 3));
+
+        framebuffer.scroll(0, 1, 1);
+        assert_eq!(framebuffer, Framebuffer::new(8, 4));
+    }
+
+    #[test]
+    fn test_scroll_sideways() {
+        let mut framebuffer = with_pixel(2, 1);
+        framebuffer.scroll(4, 0, 1);
+        assert_eq!(framebuffer, with_pixel(6, 1));
+
+        framebuffer.scroll(-6, 0, 1);
+        assert_eq!(framebuffer, with_pixel(0, 1));
+
+        framebuffer.scroll(8, 0, 1);
+        assert_eq!(framebuffer, Framebuffer::new(8, 4));
+    }
+
+    #[test]
+    fn test_planes_are_independent() {
+        let mut framebuffer = Framebuffer::new(8, 4);
+        assert!(!framebuffer.toggle(1, 1, 1));
+        assert!(!framebuffer.toggle(1, 1, 2));
+        assert!(framebuffer.toggle(1, 1, 2));
+        framebuffer.toggle(1, 1, 2);
+        assert_eq!(framebuffer.get(1, 1), 3);
+
+        framebuffer.scroll(0, -1, 2);
+        assert_eq!(framebuffer.get(1, 1), 1);
+        assert_eq!(framebuffer.get(1, 0), 2);
+
+        framebuffer.clear_planes(1);
+        assert_eq!(framebuffer.get(1, 1), 0);
+        assert_eq!(framebuffer.get(1, 0), 2);
+    }
+}
@@ -0,0 +1,508 @@
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+
+use crate::error::ExecutionError;
+use crate::input_output::InputOutput;
+use crate::system::{StepOutcome, System};
+use crate::watchpoint::{Access, WatchHit, WatchTarget, Watchpoint};
+
+/// Byte a debugger sends on its own to stop a running program.
+const INTERRUPT: u8 = 0x03;
+
+/// Largest packet the stub accepts, advertised to the debugger.
+const PACKET_SIZE: usize = 0x1000;
+
+/// Registers in the order the target description numbers them, with their size in bytes.
+const REGISTERS: [(&str, usize); 21] = [
+    ("v0", 1),
+    ("v1", 1),
+    ("v2", 1),
+    ("v3", 1),
+    ("v4", 1),
+    ("v5", 1),
+    ("v6", 1),
+    ("v7", 1),
+    ("v8", 1),
+    ("v9", 1),
+    ("va", 1),
+    ("vb", 1),
+    ("vc", 1),
+    ("vd", 1),
+    ("ve", 1),
+    ("vf", 1),
+    ("i", 2),
+    ("pc", 2),
+    ("sp", 1),
+    ("dt", 1),
+    ("st", 1),
+];
+
+const I: usize = 16;
+const PC: usize = 17;
+const SP: usize = 18;
+const DT: usize = 19;
+const ST: usize = 20;
+
+/// Why the program stopped, as reported to the debugger.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StopReason {
+    Breakpoint,
+
+    /// The debugger asked for the program to be stopped.
+    Interrupt,
+
+    /// The program exited, and cannot be resumed.
+    Exited,
+
+    /// An instruction touched a watchpoint.
+    Watchpoint(WatchHit),
+
+    /// An instruction could not be executed.
+    Error(ExecutionError),
+}
+
+impl StopReason {
+    /// Why `system` stopped after its last instruction ran.
+    fn after_step<IO: InputOutput>(system: &System<IO>) -> Self {
+        match system.watch_hits().first() {
+            Some(hit) => StopReason::Watchpoint(*hit),
+            None => StopReason::Breakpoint,
+        }
+    }
+
+    fn reply(&self) -> String {
+        match self {
+            StopReason::Breakpoint => "S05".to_string(),
+            StopReason::Interrupt => "S02".to_string(),
+            StopReason::Exited => "W00".to_string(),
+            StopReason::Watchpoint(WatchHit {
+                target: WatchTarget::Memory(address),
+                access,
+                ..
+            }) => {
+                let kind = match access {
+                    Access::Read => "rwatch",
+                    _ => "watch",
+                };
+                format!("T05{}:{:x};", kind, address)
+            }
+            // GDB only knows about watchpoints on memory.
+            StopReason::Watchpoint(_) => "S05".to_string(),
+            StopReason::Error(ExecutionError::InvalidOpcode { .. }) => "S04".to_string(),
+            StopReason::Error(_) => "S0b".to_string(),
+        }
+    }
+}
+
+/// What the front end should do once the debugger lets the program go.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resume {
+    /// Run until a breakpoint is reached, or the debugger interrupts.
+    Continue,
+
+    /// Run on without the debugger.
+    Detach,
+
+    /// Stop the emulator.
+    Kill,
+}
+
+/// Stub serving the GDB Remote Serial Protocol to a debugger connected over TCP.
+///
+/// The stub answers the debugger while the program is stopped; running it is left to the
+/// front end, which polls `interrupted` and calls `stopped` when it stops again.
+///
+/// The target description numbers V0 to VF from 0, followed by I, PC, SP, DT and ST.
+/// GDB has no CHIP-8 architecture, so I and PC are sent little-endian like on the
+/// machines it does know.
+///
+/// Source: https://sourceware.org/gdb/current/onlinedocs/gdb.html/Remote-Protocol.html
+pub struct GdbStub {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+    acknowledge: bool,
+}
+
+impl GdbStub {
+    pub fn new(stream: TcpStream) -> io::Result<Self> {
+        // Packets are small and answered one at a time, so they should not be held back.
+        stream.set_nodelay(true)?;
+        Ok(GdbStub {
+            reader: BufReader::new(stream.try_clone()?),
+            writer: stream,
+            acknowledge: true,
+        })
+    }
+
+    /// Answers the debugger until it resumes the program. A closed connection detaches.
+    pub fn serve<IO: InputOutput>(&mut self, system: &mut System<IO>) -> io::Result<Resume> {
+        loop {
+            let packet = match self.read_packet()? {
+                Some(packet) => packet,
+                None => return Ok(Resume::Detach),
+            };
+
+            let reply = match packet.as_bytes().first() {
+                Some(b'?') => StopReason::Breakpoint.reply(),
+                Some(b'g') => read_registers(system),
+                Some(b'G') => write_registers(system, &packet[1..]),
+                Some(b'p') => read_register(system, &packet[1..]),
+                Some(b'P') => write_register(system, &packet[1..]),
+                Some(b'm') => read_memory(system, &packet[1..]),
+                Some(b'M') => write_memory(system, &packet[1..]),
+                Some(b'Z') => set_breakpoint(system, &packet[1..], true),
+                Some(b'z') => set_breakpoint(system, &packet[1..], false),
+                Some(b's') => {
+                    resume_at(system, &packet[1..]);
+                    let reason = match system.step() {
+                        Ok(StepOutcome::Exited) => StopReason::Exited,
+                        Ok(_) => StopReason::after_step(system),
+                        Err(e) => StopReason::Error(e),
+                    };
+                    reason.reply()
+                }
+                Some(b'c') => {
+                    resume_at(system, &packet[1..]);
+                    return Ok(Resume::Continue);
+                }
+                Some(b'D') => {
+                    self.write_packet("OK")?;
+                    return Ok(Resume::Detach);
+                }
+                Some(b'k') => return Ok(Resume::Kill),
+                Some(b'H') => "OK".to_string(),
+                _ if packet == "QStartNoAckMode" => {
+                    // The reply to this packet is still acknowledged.
+                    self.write_packet("OK")?;
+                    self.acknowledge = false;
+                    continue;
+                }
+                _ => query(&packet),
+            };
+            self.write_packet(&reply)?;
+        }
+    }
+
+    /// Whether the debugger has asked for the running program to be stopped, without
+    /// waiting for it to.
+    pub fn interrupted(&mut self) -> io::Result<bool> {
+        if !self.reader.buffer().is_empty() {
+            return Ok(self.take_interrupt());
+        }
+
+        self.writer.set_nonblocking(true)?;
+        let result = self.reader.fill_buf().map(|buffer| buffer.len());
+        self.writer.set_nonblocking(false)?;
+
+        match result {
+            Ok(0) => Err(io::ErrorKind::ConnectionAborted.into()),
+            Ok(_) => Ok(self.take_interrupt()),
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Tells the debugger the program has stopped running.
+    pub fn stopped(&mut self, reason: &StopReason) -> io::Result<()> {
+        self.write_packet(&reason.reply())
+    }
+
+    /// Consumes whatever was sent while the program ran, looking for an interrupt.
+    fn take_interrupt(&mut self) -> bool {
+        let interrupted = self.reader.buffer().contains(&INTERRUPT);
+        let len = self.reader.buffer().len();
+        self.reader.consume(len);
+        interrupted
+    }
+
+    /// Reads the next packet, acknowledging it. Returns None when the connection closes.
+    fn read_packet(&mut self) -> io::Result<Option<String>> {
+        loop {
+            let mut byte = [0];
+            loop {
+                if self.reader.read(&mut byte)? == 0 {
+                    return Ok(None);
+                }
+                match byte[0] {
+                    b'$' => break,
+                    // The program is already stopped.
+                    INTERRUPT => self.write_packet(&StopReason::Interrupt.reply())?,
+                    _ => {}
+                }
+            }
+
+            let mut data = Vec::new();
+            loop {
+                if self.reader.read(&mut byte)? == 0 {
+                    return Ok(None);
+                }
+                match byte[0] {
+                    b'#' => break,
+                    b'}' => {
+                        self.reader.read_exact(&mut byte)?;
+                        data.push(byte[0] ^ 0x20);
+                    }
+                    other => data.push(other),
+                }
+            }
+
+            let mut checksum = [0; 2];
+            self.reader.read_exact(&mut checksum)?;
+            let valid = std::str::from_utf8(&checksum)
+                .ok()
+                .and_then(|checksum| u8::from_str_radix(checksum, 16).ok())
+                == Some(sum(&data));
+
+            if self.acknowledge {
+                self.writer.write_all(if valid { b"+" } else { b"-" })?;
+            }
+            if valid {
+                return Ok(Some(String::from_utf8_lossy(&data).into_owned()));
+            }
+        }
+    }
+
+    fn write_packet(&mut self, data: &str) -> io::Result<()> {
+        write!(self.writer, "${}#{:02x}", data, sum(data.as_bytes()))?;
+        self.writer.flush()
+    }
+}
+
+/// Answers general queries. Unsupported packets get an empty reply.
+fn query(packet: &str) -> String {
+    if packet.starts_with("qSupported") {
+        format!(
+            "PacketSize={:x};QStartNoAckMode+;qXfer:features:read+",
+            PACKET_SIZE
+        )
+    } else if let Some(range) = packet.strip_prefix("qXfer:features:read:target.xml:") {
+        match parse_range(range) {
+            Some((offset, len)) => {
+                let xml = target_description();
+                let start = offset.min(xml.len());
+                let end = start.saturating_add(len).min(xml.len());
+                let more = if end < xml.len() { 'm' } else { 'l' };
+                format!("{}{}", more, &xml[start..end])
+            }
+            None => "E01".to_string(),
+        }
+    } else if packet == "qAttached" {
+        "1".to_string()
+    } else {
+        String::new()
+    }
+}
+
+fn sum(data: &[u8]) -> u8 {
+    data.iter().fold(0, |sum, byte| sum.wrapping_add(*byte))
+}
+
+fn target_description() -> String {
+    let mut xml = String::from(
+        "<?xml version=\"1.0\"?>\
+         <!DOCTYPE target SYSTEM \"gdb-target.dtd\">\
+         <target version=\"1.0\"><feature name=\"org.chip8.core\">",
+    );
+    for (name, size) in REGISTERS {
+        let kind = match name {
+            "i" => "data_ptr",
+            "pc" => "code_ptr",
+            _ => "uint8",
+        };
+        xml += &format!(
+            "<reg name=\"{}\" bitsize=\"{}\" type=\"{}\"/>",
+            name,
+            size * 8,
+            kind
+        );
+    }
+    xml + "</feature></target>"
+}
+
+fn register<IO: InputOutput>(system: &System<IO>, n: usize) -> u16 {
+    match n {
+        0..=15 => system.registers()[n] as u16,
+        I => system.index(),
+        PC => system.program_counter(),
+        SP => system.stack_pointer() as u16,
+        DT => system.delay_timer() as u16,
+        ST => system.sound_timer() as u16,
+        _ => unreachable!("no register {}", n),
+    }
+}
+
+/// Sets register `n`. The stack pointer cannot be changed, as it has to match the stack.
+fn set_register<IO: InputOutput>(system: &mut System<IO>, n: usize, value: u16) {
+    match n {
+        0..=15 => system.registers_mut()[n] = value as u8,
+        I => system.set_index(value),
+        PC => system.set_program_counter(value),
+        DT => system.set_delay_timer(value as u8),
+        ST => system.set_sound_timer(value as u8),
+        _ => {}
+    }
+}
+
+fn encode_register<IO: InputOutput>(system: &System<IO>, n: usize) -> String {
+    let bytes = register(system, n).to_le_bytes();
+    hex(&bytes[..REGISTERS[n].1])
+}
+
+fn read_registers<IO: InputOutput>(system: &System<IO>) -> String {
+    (0..REGISTERS.len())
+        .map(|n| encode_register(system, n))
+        .collect()
+}
+
+fn write_registers<IO: InputOutput>(system: &mut System<IO>, data: &str) -> String {
+    let Some(bytes) = unhex(data) else {
+        return "E01".to_string();
+    };
+    let mut offset = 0;
+
+    for (n, (_, size)) in REGISTERS.iter().enumerate() {
+        let Some(value) = bytes.get(offset..offset + size) else {
+            break;
+        };
+        set_register(system, n, decode_value(value));
+        offset += size;
+    }
+    "OK".to_string()
+}
+
+fn read_register<IO: InputOutput>(system: &System<IO>, data: &str) -> String {
+    match usize::from_str_radix(data, 16) {
+        Ok(n) if n < REGISTERS.len() => encode_register(system, n),
+        _ => "E01".to_string(),
+    }
+}
+
+fn write_register<IO: InputOutput>(system: &mut System<IO>, data: &str) -> String {
+    let parsed = data.split_once('=').and_then(|(n, value)| {
+        let n = usize::from_str_radix(n, 16).ok()?;
+        let value = unhex(value)?;
+        (n < REGISTERS.len() && value.len() == REGISTERS[n].1).then_some((n, value))
+    });
+
+    match parsed {
+        Some((SP, _)) => "E01".to_string(),
+        Some((n, value)) => {
+            set_register(system, n, decode_value(&value));
+            "OK".to_string()
+        }
+        None => "E01".to_string(),
+    }
+}
+
+fn decode_value(bytes: &[u8]) -> u16 {
+    bytes
+        .iter()
+        .rev()
+        .fold(0, |value, byte| value << 8 | *byte as u16)
+}
+
+fn read_memory<IO: InputOutput>(system: &System<IO>, data: &str) -> String {
+    match parse_range(data)
+        .and_then(|(start, len)| system.memory().get(start..start.checked_add(len)?))
+    {
+        Some(bytes) => hex(bytes),
+        None => "E01".to_string(),
+    }
+}
+
+fn write_memory<IO: InputOutput>(system: &mut System<IO>, data: &str) -> String {
+    let parsed = data.split_once(':').and_then(|(range, bytes)| {
+        let (start, len) = parse_range(range)?;
+        let bytes = unhex(bytes)?;
+        (bytes.len() == len).then_some((start, bytes))
+    });
+
+    match parsed.and_then(|(start, bytes)| {
+        let memory = system
+            .memory_mut()
+            .get_mut(start..start.checked_add(bytes.len())?)?;
+        memory.copy_from_slice(&bytes);
+        Some(())
+    }) {
+        Some(()) => "OK".to_string(),
+        None => "E01".to_string(),
+    }
+}
+
+/// Adds or removes a breakpoint or watchpoint. Software and hardware breakpoints are the
+/// same thing to the System, and the kind of a watchpoint is the number of bytes watched.
+fn set_breakpoint<IO: InputOutput>(system: &mut System<IO>, data: &str, insert: bool) -> String {
+    // Conditions and commands evaluated by the target are not supported.
+    let data = data.split(';').next().unwrap_or_default();
+    let fields: Vec<_> = data.split(',').collect();
+    let (kind, address, len) = match fields[..] {
+        [kind, address, len] => (
+            kind,
+            u16::from_str_radix(address, 16),
+            u16::from_str_radix(len, 16),
+        ),
+        _ => return "E01".to_string(),
+    };
+    let access = match kind {
+        "0" | "1" => None,
+        "2" => Some(Access::Write),
+        "3" => Some(Access::Read),
+        "4" => Some(Access::ReadWrite),
+        _ => return String::new(),
+    };
+    let (Ok(address), Ok(len)) = (address, len) else {
+        return "E01".to_string();
+    };
+
+    match access {
+        None if insert => system.add_breakpoint(address),
+        None => {
+            system.remove_breakpoint(address);
+        }
+        Some(access) => {
+            let Some(end) = len.checked_sub(1).and_then(|len| address.checked_add(len)) else {
+                return "E01".to_string();
+            };
+            let watchpoint = Watchpoint::Memory {
+                range: address..=end,
+                access,
+            };
+            if insert {
+                system.add_watchpoint(watchpoint);
+            } else {
+                system.remove_watchpoint(&watchpoint);
+            }
+        }
+    }
+    "OK".to_string()
+}
+
+/// `s` and `c` can give the address to resume from.
+fn resume_at<IO: InputOutput>(system: &mut System<IO>, data: &str) {
+    if let Ok(address) = u16::from_str_radix(data, 16) {
+        system.set_program_counter(address);
+    }
+}
+
+/// Parses `start,len` in hexadecimal.
+fn parse_range(data: &str) -> Option<(usize, usize)> {
+    let (start, len) = data.split_once(',')?;
+    Some((
+        usize::from_str_radix(start, 16).ok()?,
+        usize::from_str_radix(len, 16).ok()?,
+    ))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn unhex(data: &str) -> Option<Vec<u8>> {
+    if !data.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..data.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(data.get(i..i + 2)?, 16).ok())
+        .collect()
+}
@@ -1,98 +1,87 @@
-use sdl2::event::{Event, EventPollIterator};
-use sdl2::keyboard::Keycode;
-use sdl2::pixels::Color;
-use sdl2::rect::Rect;
-use sdl2::render::WindowCanvas;
-use sdl2::{EventPump, VideoSubsystem};
-use std::unreachable;
-
-const DISPLAY_WIDTH: u8 = 64;
-const DISPLAY_HEIGHT: u8 = 32;
-
-pub struct InputOutput {
-    pub pixels: [u8; 32 * 64],
-    scale: u32,
-    video: VideoSubsystem,
-    canvas: WindowCanvas,
-    foreground: Color,
-    background: Color,
-    events: EventPump,
+use crate::constants::{AUDIO_PATTERN_SIZE, KEY_COUNT};
+use crate::framebuffer::Framebuffer;
+
+/// State of the 16-key hexadecimal keypad.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Keypad {
+    keys: [bool; KEY_COUNT],
 }
 
-impl Default for InputOutput {
-    fn default() -> Self {
-        let scale = 8;
-        let sdl_context = sdl2::init().unwrap();
-        let video_subsystem = sdl_context.video().unwrap();
-
-        let window = video_subsystem
-            .window(
-                "Chip8",
-                scale * DISPLAY_WIDTH as u32,
-                scale * DISPLAY_HEIGHT as u32,
-            )
-            .opengl()
-            .build()
-            .unwrap();
-
-        let canvas = window.into_canvas().build().unwrap();
-
-        InputOutput {
-            scale,
-            pixels: [0; 32 * 64],
-            video: video_subsystem,
-            canvas,
-            events: sdl_context.event_pump().unwrap(),
-            foreground: Color::RGB(150, 150, 35),
-            background: Color::RGB(20, 20, 0),
-        }
+impl Keypad {
+    pub fn is_pressed(&self, key: u8) -> bool {
+        self.keys[(key & 0xF) as usize]
     }
-}
 
-impl InputOutput {
-    pub fn present(&mut self) {
-        self.canvas.present()
+    pub fn set(&mut self, key: u8, pressed: bool) {
+        self.keys[(key & 0xF) as usize] = pressed;
     }
 
-    pub fn clear(&mut self) {
-        self.canvas.set_draw_color(self.background);
-        self.canvas.clear()
+    pub fn press(&mut self, key: u8) {
+        self.set(key, true);
     }
 
-    pub fn draw(&mut self) {
-        self.canvas.set_draw_color(self.foreground);
+    pub fn release(&mut self, key: u8) {
+        self.set(key, false);
+    }
 
-        for (i, pixel) in self.pixels.iter().enumerate() {
-            if *pixel > 0 {
-                let x = (i % 64) as u32;
-                let y = ((i / 64) % 64) as u32;
+    /// The lowest numbered key currently held down, if any.
+    pub fn first_pressed(&self) -> Option<u8> {
+        (0..KEY_COUNT as u8).find(|key| self.is_pressed(*key))
+    }
+}
 
-                let rect = Rect::from((
-                    (self.scale * x) as i32,
-                    (self.scale * y) as i32,
-                    self.scale,
-                    self.scale,
-                ));
+/// Backend that shows the framebuffer to the user.
+pub trait Display {
+    fn present(&mut self, framebuffer: &Framebuffer);
+}
 
-                self.canvas.fill_rect(rect).expect("TODO: panic message");
-            }
-        }
-    }
+/// Backend that feeds host input into the keypad.
+pub trait Input {
+    fn poll(&mut self, keypad: &mut Keypad);
+}
+
+/// Backend that plays the buzzer.
+pub trait Audio {
+    fn set_beep(&mut self, active: bool);
 
-    pub fn set_pixel(&mut self, x: u32, y: u32) {
-        self.pixels[((y * 64) + x) as usize] = 1;
+    /// Replaces the buzzer tone with an XO-CHIP audio pattern, a 1-bit waveform of 128 samples,
+    /// or goes back to the buzzer's own tone with None.
+    fn set_pattern(&mut self, pattern: Option<&[u8; AUDIO_PATTERN_SIZE]>, pitch: u8);
+}
+
+/// Everything the System needs to talk to the outside world.
+pub trait InputOutput: Display + Input + Audio {}
+
+impl<T: Display + Input + Audio> InputOutput for T {}
+
+/// In-memory backend that needs no windowing system.
+///
+/// Presented frames and buzzer state are recorded so they can be inspected afterwards.
+#[derive(Debug, Default)]
+pub struct Headless {
+    pub frame: Option<Framebuffer>,
+    pub frames_presented: u64,
+    pub beeping: bool,
+    pub pattern: Option<([u8; AUDIO_PATTERN_SIZE], u8)>,
+}
+
+impl Display for Headless {
+    fn present(&mut self, framebuffer: &Framebuffer) {
+        self.frame = Some(framebuffer.clone());
+        self.frames_presented += 1;
     }
+}
+
+impl Input for Headless {
+    fn poll(&mut self, _keypad: &mut Keypad) {}
+}
 
-    pub fn iter_events(&mut self) -> EventPollIterator {
-        self.events.poll_iter()
+impl Audio for Headless {
+    fn set_beep(&mut self, active: bool) {
+        self.beeping = active;
     }
 
-    pub fn await_keypress(&mut self) -> Keycode {
-        for event in self.events.wait_iter() {
-            if let Event::KeyDown { keycode, .. } = event {
-                return keycode.unwrap();
-            }
-        }
-        unreachable!();
+    fn set_pattern(&mut self, pattern: Option<&[u8; AUDIO_PATTERN_SIZE]>, pitch: u8) {
+        self.pattern = pattern.map(|pattern| (*pattern, pitch));
     }
 }
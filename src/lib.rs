@@ -1,6 +1,24 @@
 extern crate core;
 
+pub mod analysis;
+pub mod assembler;
+pub mod config;
 pub mod constants;
+pub mod debugger;
+pub mod disassembler;
+pub mod error;
+pub mod framebuffer;
+pub mod gdb;
 pub mod input_output;
 pub mod opcode;
+pub mod profile;
+pub mod quirks;
+pub mod random;
+pub mod rewind;
+pub mod save_state;
+#[cfg(feature = "sdl")]
+pub mod sdl;
 pub mod system;
+pub mod timing;
+pub mod trace;
+pub mod watchpoint;
@@ -1,11 +1,13 @@
+use crate::error::EncodeError;
+
 /// OpCodes of the Chip-8 Virtual Machine.
 ///
 /// Source: https://en.wikipedia.org/wiki/CHIP-8#Opcode_table
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Operation {
-    /// Code: 0NNN
+    /// Code: 0000
     ///
-    /// No Operation.
+    /// No Operation. Other 0NNN machine code routines are not supported.
     NoOperation,
 
     /// Code: 00E0
@@ -129,7 +131,8 @@ pub enum Operation {
     /// Each row of 8 pixels is read as bit-coded starting from memory location I;
     /// I value does not change after the execution of this instruction. As described above,
     /// VF is set to 1 if any screen pixels are flipped from set to unset when the sprite is drawn,
-    /// and to 0 if that does not happen
+    /// and to 0 if that does not happen.
+    /// (SUPER-CHIP: When N is 0, a 16x16 sprite is drawn from 32 bytes at I);
     DrawSprite { x: u8, y: u8, n: u8 },
 
     /// Code: EX9E
@@ -197,6 +200,180 @@ pub enum Operation {
     /// Fills from V0 to VX (including VX) with values from memory, starting at address I.
     /// The offset from I is increased by 1 for each value read, but I itself is left unmodified.
     SetRegistersFromMemory { x: u8 },
+
+    /// Code: 00CN
+    ///
+    /// Scrolls the display down by N pixels. (SUPER-CHIP);
+    ScrollDown { n: u8 },
+
+    /// Code: 00FB
+    ///
+    /// Scrolls the display right by 4 pixels. (SUPER-CHIP);
+    ScrollRight,
+
+    /// Code: 00FC
+    ///
+    /// Scrolls the display left by 4 pixels. (SUPER-CHIP);
+    ScrollLeft,
+
+    /// Code: 00FD
+    ///
+    /// Exits the interpreter. (SUPER-CHIP);
+    Exit,
+
+    /// Code: 00FE
+    ///
+    /// Switches to the 64x32 low resolution display. (SUPER-CHIP);
+    LowResolution,
+
+    /// Code: 00FF
+    ///
+    /// Switches to the 128x64 high resolution display. (SUPER-CHIP);
+    HighResolution,
+
+    /// Code: FX30
+    ///
+    /// Sets I to the location of the 8x10 sprite for the character in VX. (SUPER-CHIP);
+    SetIndexToLargeSprite { x: u8 },
+
+    /// Code: FX75
+    ///
+    /// Stores from V0 to VX (including VX) in the RPL user flags. (SUPER-CHIP);
+    StoreRegistersInFlags { x: u8 },
+
+    /// Code: FX85
+    ///
+    /// Fills from V0 to VX (including VX) with values from the RPL user flags. (SUPER-CHIP);
+    SetRegistersFromFlags { x: u8 },
+
+    /// Code: 00DN
+    ///
+    /// Scrolls the display up by N pixels. (XO-CHIP);
+    ScrollUp { n: u8 },
+
+    /// Code: 5XY2
+    ///
+    /// Stores VX to VY (including VY) in memory, starting at address I.
+    /// Registers are stored in reverse order when X is greater than Y. I is left unmodified.
+    /// (XO-CHIP);
+    StoreRegisterRange { x: u8, y: u8 },
+
+    /// Code: 5XY3
+    ///
+    /// Fills VX to VY (including VY) with values from memory, starting at address I.
+    /// Registers are loaded in reverse order when X is greater than Y. I is left unmodified.
+    /// (XO-CHIP);
+    LoadRegisterRange { x: u8, y: u8 },
+
+    /// Code: F000 NNNN
+    ///
+    /// Sets I to the 16-bit address NNNN stored in the word after this instruction.
+    /// (XO-CHIP: This is the only instruction that is 4 bytes long);
+    SetIndexToLongAddress,
+
+    /// Code: FN01
+    ///
+    /// Selects the bitplanes N drawn to and cleared by subsequent instructions. (XO-CHIP);
+    SelectPlanes { x: u8 },
+
+    /// Code: F002
+    ///
+    /// Stores 16 bytes starting at address I in the audio pattern buffer. (XO-CHIP);
+    StoreAudioPattern,
+
+    /// Code: FX3A
+    ///
+    /// Sets the audio playback pitch to VX. (XO-CHIP);
+    SetPitch { x: u8 },
+}
+
+impl Operation {
+    /// Whether the operation only exists on SUPER-CHIP and later interpreters.
+    pub fn is_super_chip(&self) -> bool {
+        matches!(
+            self,
+            Operation::ScrollDown { .. }
+                | Operation::ScrollRight
+                | Operation::ScrollLeft
+                | Operation::Exit
+                | Operation::LowResolution
+                | Operation::HighResolution
+                | Operation::SetIndexToLargeSprite { .. }
+                | Operation::StoreRegistersInFlags { .. }
+                | Operation::SetRegistersFromFlags { .. }
+        )
+    }
+
+    /// Whether the operation only exists on XO-CHIP.
+    pub fn is_xo_chip(&self) -> bool {
+        matches!(
+            self,
+            Operation::ScrollUp { .. }
+                | Operation::StoreRegisterRange { .. }
+                | Operation::LoadRegisterRange { .. }
+                | Operation::SetIndexToLongAddress
+                | Operation::SelectPlanes { .. }
+                | Operation::StoreAudioPattern
+                | Operation::SetPitch { .. }
+        )
+    }
+
+    /// Name of the kind of operation, which is the same whatever its operands are.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Operation::NoOperation => "NoOperation",
+            Operation::ClearDisplay => "ClearDisplay",
+            Operation::SubroutineReturn => "SubroutineReturn",
+            Operation::GotoAddress { .. } => "GotoAddress",
+            Operation::SubroutineCall { .. } => "SubroutineCall",
+            Operation::EqualityCheck { .. } => "EqualityCheck",
+            Operation::InequalityCheck { .. } => "InequalityCheck",
+            Operation::EqualityRegisterCheck { .. } => "EqualityRegisterCheck",
+            Operation::SetRegister { .. } => "SetRegister",
+            Operation::AddRegister { .. } => "AddRegister",
+            Operation::SetRegisterFromRegister { .. } => "SetRegisterFromRegister",
+            Operation::BitwiseOr { .. } => "BitwiseOr",
+            Operation::BitwiseAnd { .. } => "BitwiseAnd",
+            Operation::BitwiseXor { .. } => "BitwiseXor",
+            Operation::AddValues { .. } => "AddValues",
+            Operation::SubtractValues { .. } => "SubtractValues",
+            Operation::StoreLeastSignificant { .. } => "StoreLeastSignificant",
+            Operation::SubtractValueFromRegister { .. } => "SubtractValueFromRegister",
+            Operation::StoreMostSignificant { .. } => "StoreMostSignificant",
+            Operation::InequalityRegisterCheck { .. } => "InequalityRegisterCheck",
+            Operation::SetIndexToAddress { .. } => "SetIndexToAddress",
+            Operation::GotoAddressWithRegister { .. } => "GotoAddressWithRegister",
+            Operation::AssignRandomNumber { .. } => "AssignRandomNumber",
+            Operation::DrawSprite { .. } => "DrawSprite",
+            Operation::SkipIfKeyPressed { .. } => "SkipIfKeyPressed",
+            Operation::SkipIfKeyNotPressed { .. } => "SkipIfKeyNotPressed",
+            Operation::GetDelayTimer { .. } => "GetDelayTimer",
+            Operation::StoreNextKeypress { .. } => "StoreNextKeypress",
+            Operation::SetDelayTimer { .. } => "SetDelayTimer",
+            Operation::SetSoundTimer { .. } => "SetSoundTimer",
+            Operation::AddToIndex { .. } => "AddToIndex",
+            Operation::SetIndexToSprite { .. } => "SetIndexToSprite",
+            Operation::StoreBinaryCodedDecimal { .. } => "StoreBinaryCodedDecimal",
+            Operation::StoreRegistersInMemory { .. } => "StoreRegistersInMemory",
+            Operation::SetRegistersFromMemory { .. } => "SetRegistersFromMemory",
+            Operation::ScrollDown { .. } => "ScrollDown",
+            Operation::ScrollRight => "ScrollRight",
+            Operation::ScrollLeft => "ScrollLeft",
+            Operation::Exit => "Exit",
+            Operation::LowResolution => "LowResolution",
+            Operation::HighResolution => "HighResolution",
+            Operation::SetIndexToLargeSprite { .. } => "SetIndexToLargeSprite",
+            Operation::StoreRegistersInFlags { .. } => "StoreRegistersInFlags",
+            Operation::SetRegistersFromFlags { .. } => "SetRegistersFromFlags",
+            Operation::ScrollUp { .. } => "ScrollUp",
+            Operation::StoreRegisterRange { .. } => "StoreRegisterRange",
+            Operation::LoadRegisterRange { .. } => "LoadRegisterRange",
+            Operation::SetIndexToLongAddress => "SetIndexToLongAddress",
+            Operation::SelectPlanes { .. } => "SelectPlanes",
+            Operation::StoreAudioPattern => "StoreAudioPattern",
+            Operation::SetPitch { .. } => "SetPitch",
+        }
+    }
 }
 
 #[inline]
@@ -231,12 +408,24 @@ fn parse_nnn(opcode: u16) -> u16 {
 }
 
 /// Decode u16 into Chip-8 opcode.
+#[allow(clippy::result_unit_err)]
 pub fn decode(opcode: u16) -> Result<Operation, ()> {
     match opcode & 0xF000 {
-        0x0000 => match opcode & 0x00FF {
+        0x0000 => match opcode {
             0x0000 => Ok(Operation::NoOperation),
             0x00E0 => Ok(Operation::ClearDisplay),
             0x00EE => Ok(Operation::SubroutineReturn),
+            0x00C0..=0x00CF => Ok(Operation::ScrollDown {
+                n: (opcode & 0x000F) as u8,
+            }),
+            0x00D0..=0x00DF => Ok(Operation::ScrollUp {
+                n: (opcode & 0x000F) as u8,
+            }),
+            0x00FB => Ok(Operation::ScrollRight),
+            0x00FC => Ok(Operation::ScrollLeft),
+            0x00FD => Ok(Operation::Exit),
+            0x00FE => Ok(Operation::LowResolution),
+            0x00FF => Ok(Operation::HighResolution),
             _ => Err(()),
         },
         0x1000 => Ok(Operation::GotoAddress {
@@ -255,7 +444,12 @@ pub fn decode(opcode: u16) -> Result<Operation, ()> {
         }
         0x5000 => {
             let (x, y) = parse_x_y(opcode);
-            Ok(Operation::EqualityRegisterCheck { x, y })
+            match opcode & 0x000F {
+                0x0000 => Ok(Operation::EqualityRegisterCheck { x, y }),
+                0x0002 => Ok(Operation::StoreRegisterRange { x, y }),
+                0x0003 => Ok(Operation::LoadRegisterRange { x, y }),
+                _ => Err(()),
+            }
         }
         0x6000 => {
             let (x, nn) = parse_x_nn(opcode);
@@ -282,7 +476,10 @@ pub fn decode(opcode: u16) -> Result<Operation, ()> {
         }
         0x9000 => {
             let (x, y) = parse_x_y(opcode);
-            Ok(Operation::InequalityRegisterCheck { x, y })
+            match opcode & 0x000F {
+                0x0000 => Ok(Operation::InequalityRegisterCheck { x, y }),
+                _ => Err(()),
+            }
         }
         0xA000 => Ok(Operation::SetIndexToAddress {
             nnn: parse_nnn(opcode),
@@ -309,15 +506,22 @@ pub fn decode(opcode: u16) -> Result<Operation, ()> {
         0xF000 => {
             let x = parse_x(opcode);
             match opcode & 0x00FF {
+                0x0000 if x == 0 => Ok(Operation::SetIndexToLongAddress),
+                0x0001 => Ok(Operation::SelectPlanes { x }),
+                0x0002 if x == 0 => Ok(Operation::StoreAudioPattern),
                 0x0007 => Ok(Operation::GetDelayTimer { x }),
                 0x000A => Ok(Operation::StoreNextKeypress { x }),
                 0x0015 => Ok(Operation::SetDelayTimer { x }),
                 0x0018 => Ok(Operation::SetSoundTimer { x }),
                 0x001E => Ok(Operation::AddToIndex { x }),
                 0x0029 => Ok(Operation::SetIndexToSprite { x }),
+                0x0030 => Ok(Operation::SetIndexToLargeSprite { x }),
                 0x0033 => Ok(Operation::StoreBinaryCodedDecimal { x }),
+                0x003A => Ok(Operation::SetPitch { x }),
                 0x0055 => Ok(Operation::StoreRegistersInMemory { x }),
                 0x0065 => Ok(Operation::SetRegistersFromMemory { x }),
+                0x0075 => Ok(Operation::StoreRegistersInFlags { x }),
+                0x0085 => Ok(Operation::SetRegistersFromFlags { x }),
                 _ => Err(()),
             }
         }
@@ -325,6 +529,88 @@ pub fn decode(opcode: u16) -> Result<Operation, ()> {
     }
 }
 
+/// Encodes an operation as the 16-bit opcode `decode` turns back into it.
+///
+/// Fails when a field does not fit in its part of the opcode, such as an NNN above 0xFFF
+/// or a register above VF. The address following F000 is not part of the operation.
+pub fn encode(operation: &Operation) -> Result<u16, EncodeError> {
+    let check = |field: &'static str, value: u16, max: u16| {
+        if value > max {
+            Err(EncodeError { field, value, max })
+        } else {
+            Ok(value)
+        }
+    };
+    let x = |x: u8| check("x", x as u16, 0xF).map(|x| x << 8);
+    let y = |y: u8| check("y", y as u16, 0xF).map(|y| y << 4);
+    let n = |n: u8| check("n", n as u16, 0xF);
+    let nnn = |nnn: u16| check("nnn", nnn, 0xFFF);
+    let x_nn = |base: u16, vx: u8, nn: u8| Ok(base | x(vx)? | nn as u16);
+    let x_y = |base: u16, vx: u8, vy: u8| Ok(base | x(vx)? | y(vy)?);
+
+    match *operation {
+        Operation::NoOperation => Ok(0x0000),
+        Operation::ClearDisplay => Ok(0x00E0),
+        Operation::SubroutineReturn => Ok(0x00EE),
+        Operation::GotoAddress { nnn: address } => Ok(0x1000 | nnn(address)?),
+        Operation::SubroutineCall { nnn: address } => Ok(0x2000 | nnn(address)?),
+        Operation::EqualityCheck { x, nn } => x_nn(0x3000, x, nn),
+        Operation::InequalityCheck { x, nn } => x_nn(0x4000, x, nn),
+        Operation::EqualityRegisterCheck { x, y } => x_y(0x5000, x, y),
+        Operation::SetRegister { x, nn } => x_nn(0x6000, x, nn),
+        Operation::AddRegister { x, nn } => x_nn(0x7000, x, nn),
+        Operation::SetRegisterFromRegister { x, y } => x_y(0x8000, x, y),
+        Operation::BitwiseOr { x, y } => x_y(0x8001, x, y),
+        Operation::BitwiseAnd { x, y } => x_y(0x8002, x, y),
+        Operation::BitwiseXor { x, y } => x_y(0x8003, x, y),
+        Operation::AddValues { x, y } => x_y(0x8004, x, y),
+        Operation::SubtractValues { x, y } => x_y(0x8005, x, y),
+        Operation::StoreLeastSignificant { x, y } => x_y(0x8006, x, y),
+        Operation::SubtractValueFromRegister { x, y } => x_y(0x8007, x, y),
+        Operation::StoreMostSignificant { x, y } => x_y(0x800E, x, y),
+        Operation::InequalityRegisterCheck { x, y } => x_y(0x9000, x, y),
+        Operation::SetIndexToAddress { nnn: address } => Ok(0xA000 | nnn(address)?),
+        Operation::GotoAddressWithRegister { nnn: address } => Ok(0xB000 | nnn(address)?),
+        Operation::AssignRandomNumber { x, nn } => x_nn(0xC000, x, nn),
+        Operation::DrawSprite { x, y, n: rows } => x_y(0xD000 | n(rows)?, x, y),
+        Operation::SkipIfKeyPressed { x } => x_nn(0xE09E, x, 0),
+        Operation::SkipIfKeyNotPressed { x } => x_nn(0xE0A1, x, 0),
+        Operation::GetDelayTimer { x } => x_nn(0xF007, x, 0),
+        Operation::StoreNextKeypress { x } => x_nn(0xF00A, x, 0),
+        Operation::SetDelayTimer { x } => x_nn(0xF015, x, 0),
+        Operation::SetSoundTimer { x } => x_nn(0xF018, x, 0),
+        Operation::AddToIndex { x } => x_nn(0xF01E, x, 0),
+        Operation::SetIndexToSprite { x } => x_nn(0xF029, x, 0),
+        Operation::StoreBinaryCodedDecimal { x } => x_nn(0xF033, x, 0),
+        Operation::StoreRegistersInMemory { x } => x_nn(0xF055, x, 0),
+        Operation::SetRegistersFromMemory { x } => x_nn(0xF065, x, 0),
+        Operation::ScrollDown { n: rows } => Ok(0x00C0 | n(rows)?),
+        Operation::ScrollRight => Ok(0x00FB),
+        Operation::ScrollLeft => Ok(0x00FC),
+        Operation::Exit => Ok(0x00FD),
+        Operation::LowResolution => Ok(0x00FE),
+        Operation::HighResolution => Ok(0x00FF),
+        Operation::SetIndexToLargeSprite { x } => x_nn(0xF030, x, 0),
+        Operation::StoreRegistersInFlags { x } => x_nn(0xF075, x, 0),
+        Operation::SetRegistersFromFlags { x } => x_nn(0xF085, x, 0),
+        Operation::ScrollUp { n: rows } => Ok(0x00D0 | n(rows)?),
+        Operation::StoreRegisterRange { x, y } => x_y(0x5002, x, y),
+        Operation::LoadRegisterRange { x, y } => x_y(0x5003, x, y),
+        Operation::SetIndexToLongAddress => Ok(0xF000),
+        Operation::SelectPlanes { x } => x_nn(0xF001, x, 0),
+        Operation::StoreAudioPattern => Ok(0xF002),
+        Operation::SetPitch { x } => x_nn(0xF03A, x, 0),
+    }
+}
+
+impl TryFrom<Operation> for u16 {
+    type Error = EncodeError;
+
+    fn try_from(operation: Operation) -> Result<Self, Self::Error> {
+        encode(&operation)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use rstest::*;
@@ -364,7 +650,9 @@ mod tests {
         #[case] opcode: u16,
         #[case] expected_x: u8,
         #[case] expected_y: u8,
-        #[case] expected_n: u8,
+        #[case]
+        #[allow(unused_variables)]
+        expected_n: u8,
     ) {
         assert_eq!(parse_x_y(opcode), (expected_x, expected_y));
     }
@@ -0,0 +1,363 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::opcode::Operation;
+
+/// How many times something was executed, and the cycles that took.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Counts {
+    pub executed: u64,
+    pub cycles: u64,
+}
+
+impl Counts {
+    fn add(&mut self, cycles: u64) {
+        self.executed += 1;
+        self.cycles += cycles;
+    }
+}
+
+/// Time spent in a subroutine, found by following calls and returns.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Subroutine {
+    pub address: u16,
+    pub calls: u64,
+
+    /// Cycles spent in the subroutine and anything it called.
+    pub cycles: u64,
+
+    /// Cycles spent in the subroutine itself.
+    pub self_cycles: u64,
+}
+
+/// Counts the instructions a System executes, by kind of operation and by address, and
+/// the cycles spent in each subroutine.
+///
+/// Subroutines are followed through SubroutineCall and SubroutineReturn. A call is counted
+/// towards the caller, and a return towards the subroutine it leaves.
+#[derive(Debug, Clone, Default)]
+pub struct Profiler {
+    ops: u64,
+    cycles: u64,
+    operations: BTreeMap<&'static str, Counts>,
+    addresses: BTreeMap<u16, (Operation, Counts)>,
+    calls: BTreeMap<u16, u64>,
+
+    /// Subroutines being executed, innermost last.
+    stack: Vec<u16>,
+
+    /// Cycles spent with each stack of subroutines.
+    stacks: BTreeMap<Vec<u16>, u64>,
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Profiler::default()
+    }
+
+    /// Counts `operation` at `address`, which took `cycles` to execute.
+    pub fn record(&mut self, address: u16, operation: &Operation, cycles: u64) {
+        self.ops += 1;
+        self.cycles += cycles;
+        self.operations
+            .entry(operation.name())
+            .or_default()
+            .add(cycles);
+        // Programs can rewrite their own code, so the label is whatever ran there last.
+        let (latest, counts) = self
+            .addresses
+            .entry(address)
+            .or_insert((*operation, Counts::default()));
+        *latest = *operation;
+        counts.add(cycles);
+
+        match self.stacks.get_mut(&self.stack) {
+            Some(stack_cycles) => *stack_cycles += cycles,
+            None => {
+                self.stacks.insert(self.stack.clone(), cycles);
+            }
+        }
+
+        match operation {
+            Operation::SubroutineCall { nnn } => {
+                self.stack.push(*nnn);
+                *self.calls.entry(*nnn).or_default() += 1;
+            }
+            Operation::SubroutineReturn => {
+                self.stack.pop();
+            }
+            _ => {}
+        }
+    }
+
+    /// Instructions counted.
+    pub fn ops(&self) -> u64 {
+        self.ops
+    }
+
+    /// Cycles spent on the instructions counted.
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    /// Counts for each kind of operation executed, by name.
+    pub fn operations(&self) -> &BTreeMap<&'static str, Counts> {
+        &self.operations
+    }
+
+    /// Counts for each address an instruction was executed at, with the instruction last
+    /// executed there.
+    pub fn addresses(&self) -> &BTreeMap<u16, (Operation, Counts)> {
+        &self.addresses
+    }
+
+    /// Every subroutine called, in order of address.
+    pub fn subroutines(&self) -> Vec<Subroutine> {
+        let mut subroutines: BTreeMap<u16, Subroutine> = self
+            .calls
+            .iter()
+            .map(|(&address, &calls)| {
+                let subroutine = Subroutine {
+                    address,
+                    calls,
+                    cycles: 0,
+                    self_cycles: 0,
+                };
+                (address, subroutine)
+            })
+            .collect();
+
+        for (stack, &cycles) in &self.stacks {
+            for (depth, address) in stack.iter().enumerate() {
+                // Recursive calls only count once towards the total.
+                if !stack[..depth].contains(address) {
+                    if let Some(subroutine) = subroutines.get_mut(address) {
+                        subroutine.cycles += cycles;
+                    }
+                }
+            }
+            if let Some(subroutine) = stack.last().and_then(|last| subroutines.get_mut(last)) {
+                subroutine.self_cycles += cycles;
+            }
+        }
+        subroutines.into_values().collect()
+    }
+
+    /// A report of where the most cycles went, listing up to `top` addresses and
+    /// subroutines.
+    pub fn report(&self, top: usize) -> Report<'_> {
+        Report {
+            profiler: self,
+            top,
+        }
+    }
+
+    /// The cycles spent in each stack of subroutines, in the collapsed stack format
+    /// flame graph tools read.
+    pub fn collapsed(&self) -> Collapsed<'_> {
+        Collapsed(self)
+    }
+
+    fn percent(&self, cycles: u64) -> f64 {
+        if self.cycles == 0 {
+            0.0
+        } else {
+            100.0 * cycles as f64 / self.cycles as f64
+        }
+    }
+}
+
+/// Hot spots in a profile, most cycles first.
+pub struct Report<'a> {
+    profiler: &'a Profiler,
+    top: usize,
+}
+
+impl fmt::Display for Report<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let profiler = self.profiler;
+        writeln!(
+            f,
+            "{} instructions in {} cycles",
+            profiler.ops, profiler.cycles
+        )?;
+
+        let mut addresses: Vec<_> = profiler.addresses.iter().collect();
+        addresses.sort_by_key(|(_, (_, counts))| std::cmp::Reverse(counts.cycles));
+        writeln!(f, "\nAddresses:")?;
+        writeln!(
+            f,
+            "  {:<7} {:>10} {:>12} {:>7}  instruction",
+            "address", "executed", "cycles", "%"
+        )?;
+        for (address, (operation, counts)) in addresses.into_iter().take(self.top) {
+            writeln!(
+                f,
+                "  {:<7} {:>10} {:>12} {:>6.2}%  {}",
+                format!("{:#05X}", address),
+                counts.executed,
+                counts.cycles,
+                profiler.percent(counts.cycles),
+                operation
+            )?;
+        }
+
+        let mut operations: Vec<_> = profiler.operations.iter().collect();
+        operations.sort_by_key(|(_, counts)| std::cmp::Reverse(counts.cycles));
+        writeln!(f, "\nOperations:")?;
+        writeln!(
+            f,
+            "  {:<26} {:>10} {:>12} {:>7}",
+            "operation", "executed", "cycles", "%"
+        )?;
+        for (name, counts) in operations {
+            writeln!(
+                f,
+                "  {:<26} {:>10} {:>12} {:>6.2}%",
+                name,
+                counts.executed,
+                counts.cycles,
+                profiler.percent(counts.cycles)
+            )?;
+        }
+
+        let mut subroutines = profiler.subroutines();
+        if subroutines.is_empty() {
+            return Ok(());
+        }
+        subroutines.sort_by_key(|subroutine| std::cmp::Reverse(subroutine.cycles));
+        writeln!(f, "\nSubroutines:")?;
+        writeln!(
+            f,
+            "  {:<7} {:>10} {:>12} {:>7} {:>12} {:>7}",
+            "address", "calls", "cycles", "%", "self", "%"
+        )?;
+        for subroutine in subroutines.into_iter().take(self.top) {
+            writeln!(
+                f,
+                "  {:<7} {:>10} {:>12} {:>6.2}% {:>12} {:>6.2}%",
+                format!("{:#05X}", subroutine.address),
+                subroutine.calls,
+                subroutine.cycles,
+                profiler.percent(subroutine.cycles),
+                subroutine.self_cycles,
+                profiler.percent(subroutine.self_cycles)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// One line per stack of subroutines, such as `main;0x240;0x2A6 1200`, with the cycles
+/// spent in the innermost one.
+pub struct Collapsed<'a>(&'a Profiler);
+
+impl fmt::Display for Collapsed<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (stack, cycles) in &self.0.stacks {
+            write!(f, "main")?;
+            for address in stack {
+                write!(f, ";{:#05X}", address)?;
+            }
+            writeln!(f, " {}", cycles)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Calls 0x300 twice, which calls 0x310, each instruction taking one cycle.
+    fn profile() -> Profiler {
+        let call = |nnn| Operation::SubroutineCall { nnn };
+        let add = Operation::AddRegister { x: 0, nn: 1 };
+        let mut profiler = Profiler::new();
+
+        for _ in 0..2 {
+            profiler.record(0x200, &call(0x300), 1);
+            profiler.record(0x300, &call(0x310), 1);
+            profiler.record(0x310, &add, 1);
+            profiler.record(0x312, &Operation::SubroutineReturn, 1);
+            profiler.record(0x302, &Operation::SubroutineReturn, 1);
+        }
+        profiler.record(0x202, &add, 1);
+        profiler
+    }
+
+    #[test]
+    fn test_counts_operations_and_addresses() {
+        let profiler = profile();
+
+        assert_eq!((profiler.ops(), profiler.cycles()), (11, 11));
+        assert_eq!(
+            profiler.operations()["SubroutineCall"],
+            Counts {
+                executed: 4,
+                cycles: 4
+            }
+        );
+        assert_eq!(profiler.operations()["AddRegister"].executed, 3);
+        assert_eq!(profiler.addresses()[&0x310].1.executed, 2);
+    }
+
+    #[test]
+    fn test_addresses_show_the_latest_instruction() {
+        let mut profiler = Profiler::new();
+        profiler.record(0x200, &Operation::AddRegister { x: 0, nn: 1 }, 1);
+        profiler.record(0x200, &Operation::SetRegister { x: 0, nn: 2 }, 1);
+
+        assert_eq!(
+            profiler.addresses()[&0x200],
+            (
+                Operation::SetRegister { x: 0, nn: 2 },
+                Counts {
+                    executed: 2,
+                    cycles: 2
+                }
+            )
+        );
+    }
+
+    #[test]
+    fn test_attributes_cycles_to_subroutines() {
+        assert_eq!(
+            profile().subroutines(),
+            [
+                Subroutine {
+                    address: 0x300,
+                    calls: 2,
+                    cycles: 8,
+                    self_cycles: 4,
+                },
+                Subroutine {
+                    address: 0x310,
+                    calls: 2,
+                    cycles: 4,
+                    self_cycles: 4,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_collapsed_stacks() {
+        assert_eq!(
+            profile().collapsed().to_string(),
+            "main 3\nmain;0x300 4\nmain;0x300;0x310 4\n"
+        );
+    }
+
+    #[test]
+    fn test_report_lists_hottest_first() {
+        let report = profile().report(1).to_string();
+
+        assert!(report.starts_with("11 instructions in 11 cycles\n"));
+        assert!(report.contains("\n  0x200            2            2  18.18%  CALL 0x300\n"));
+        assert!(!report.contains("0x202"));
+        assert!(
+            report.contains("\n  0x300            2            8  72.73%            4  36.36%\n")
+        );
+    }
+}
@@ -0,0 +1,83 @@
+/// Behaviours that differ between CHIP-8 interpreters.
+///
+/// ROMs are usually written against one particular interpreter, so they only run
+/// correctly when the matching quirks are enabled.
+///
+/// Source: https://github.com/Timendus/chip8-test-suite#quirks-test
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quirks {
+    /// 8XY1, 8XY2 and 8XY3 reset VF to zero.
+    pub vf_reset: bool,
+
+    /// FX55 and FX65 leave I pointing just past the last register transferred.
+    pub memory_increment: bool,
+
+    /// 8XY6 and 8XYE shift VX in place, instead of storing VY shifted into VX.
+    pub shift_in_place: bool,
+
+    /// BXNN jumps to XNN plus VX, instead of BNNN jumping to NNN plus V0.
+    pub jump_with_vx: bool,
+
+    /// Sprites running off the edge of the screen wrap around, instead of being clipped.
+    pub sprite_wrap: bool,
+
+    /// DXYN waits for the next 60 Hz timer tick before drawing.
+    pub display_wait: bool,
+}
+
+impl Default for Quirks {
+    /// Shifts VX in place and otherwise follows the VIP, without waiting for the display.
+    fn default() -> Self {
+        Quirks {
+            vf_reset: false,
+            memory_increment: false,
+            shift_in_place: true,
+            jump_with_vx: false,
+            sprite_wrap: false,
+            display_wait: false,
+        }
+    }
+}
+
+impl Quirks {
+    /// The original interpreter on the COSMAC VIP.
+    pub fn vip() -> Self {
+        Quirks {
+            vf_reset: true,
+            memory_increment: true,
+            shift_in_place: false,
+            jump_with_vx: false,
+            sprite_wrap: false,
+            display_wait: true,
+        }
+    }
+
+    /// CHIP-48 on the HP-48 calculators.
+    pub fn chip48() -> Self {
+        Quirks {
+            vf_reset: false,
+            memory_increment: false,
+            shift_in_place: true,
+            jump_with_vx: true,
+            sprite_wrap: false,
+            display_wait: false,
+        }
+    }
+
+    /// SUPER-CHIP 1.1, which inherited its behaviour from CHIP-48.
+    pub fn schip() -> Self {
+        Quirks::chip48()
+    }
+
+    /// XO-CHIP, as implemented by Octo.
+    pub fn xochip() -> Self {
+        Quirks {
+            vf_reset: false,
+            memory_increment: true,
+            shift_in_place: false,
+            jump_with_vx: false,
+            sprite_wrap: true,
+            display_wait: false,
+        }
+    }
+}
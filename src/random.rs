@@ -0,0 +1,71 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Source of the random numbers CXNN masks into a register.
+///
+/// Programs can only observe one byte at a time, so that is all a source has to provide.
+pub trait RandomSource {
+    fn next_byte(&mut self) -> u8;
+}
+
+/// Small xorshift generator, good enough for games and fully reproducible from its seed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct XorShift {
+    state: u32,
+}
+
+impl XorShift {
+    pub fn new(seed: u64) -> Self {
+        // Spread the seed over all bits with a SplitMix64 round, so nearby seeds give
+        // unrelated sequences. Xorshift never leaves a zero state, so that one is avoided.
+        let mut z = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^= z >> 31;
+
+        XorShift {
+            state: (z as u32 ^ (z >> 32) as u32).max(1),
+        }
+    }
+
+    /// Seeded from the clock, for when runs do not need to be repeatable.
+    pub fn from_time() -> Self {
+        XorShift::new(
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_nanos() as u64),
+        )
+    }
+}
+
+impl RandomSource for XorShift {
+    fn next_byte(&mut self) -> u8 {
+        let mut state = self.state;
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+        self.state = state;
+        (state >> 24) as u8
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bytes(seed: u64) -> Vec<u8> {
+        let mut random = XorShift::new(seed);
+        (0..64).map(|_| random.next_byte()).collect()
+    }
+
+    #[test]
+    fn test_same_seed_repeats() {
+        assert_eq!(bytes(42), bytes(42));
+        assert_ne!(bytes(42), bytes(43));
+    }
+
+    #[test]
+    fn test_zero_seed_is_usable() {
+        let values = bytes(0);
+        assert!(values.iter().any(|value| *value != values[0]));
+    }
+}
@@ -0,0 +1,216 @@
+use std::collections::VecDeque;
+
+use crate::save_state::SaveState;
+
+/// Number of frames between full copies of memory.
+const KEYFRAME_INTERVAL: usize = 60;
+
+/// A recorded frame. Keyframes hold all of memory, the frames after them only the bytes
+/// that differ from it, as programs rarely write to more than a handful of addresses.
+#[derive(Debug, Clone)]
+struct Snapshot {
+    state: SaveState,
+    delta: Option<Vec<(u16, u8)>>,
+}
+
+/// Bounded history of machine states, one per frame, newest last.
+///
+/// Once full, the oldest frames are forgotten as new ones are pushed.
+#[derive(Debug, Clone, Default)]
+pub struct RewindBuffer {
+    capacity: usize,
+    snapshots: VecDeque<Snapshot>,
+}
+
+impl RewindBuffer {
+    pub fn new(capacity: usize) -> Self {
+        RewindBuffer {
+            capacity,
+            snapshots: VecDeque::new(),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn len(&self) -> usize {
+        self.snapshots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.snapshots.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.snapshots.clear();
+    }
+
+    pub fn push(&mut self, mut state: SaveState) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        let keyframe = self
+            .keyframe_position()
+            .filter(|position| self.snapshots.len() - position < KEYFRAME_INTERVAL)
+            .map(|position| &self.snapshots[position])
+            .filter(|keyframe| keyframe.state.memory.len() == state.memory.len());
+
+        let delta = keyframe.map(|keyframe| {
+            let delta = diff(&keyframe.state.memory, &state.memory);
+            state.memory = Vec::new();
+            delta
+        });
+        self.snapshots.push_back(Snapshot { state, delta });
+
+        while self.snapshots.len() > self.capacity {
+            let oldest = self.snapshots.pop_front().unwrap();
+
+            // The front of the buffer always has to be a keyframe, for the frames after it.
+            let promoted = match self.snapshots.front_mut() {
+                Some(front) if front.delta.is_some() => front.delta.take().unwrap(),
+                _ => continue,
+            };
+            let memory = patch(&oldest.state.memory, &promoted);
+
+            for snapshot in self.snapshots.iter_mut().skip(1) {
+                match &mut snapshot.delta {
+                    Some(delta) => {
+                        *delta = rebase(&oldest.state.memory, &memory, &promoted, delta);
+                    }
+                    None => break,
+                }
+            }
+            self.snapshots[0].state.memory = memory;
+        }
+    }
+
+    /// Removes and returns the newest frame.
+    pub fn pop(&mut self) -> Option<SaveState> {
+        let keyframe = self.keyframe_position()?;
+        let snapshot = self.snapshots.pop_back()?;
+
+        Some(match snapshot.delta {
+            Some(delta) => SaveState {
+                memory: patch(&self.snapshots[keyframe].state.memory, &delta),
+                ..snapshot.state
+            },
+            None => snapshot.state,
+        })
+    }
+
+    /// Position of the keyframe the newest frame is stored against.
+    fn keyframe_position(&self) -> Option<usize> {
+        self.snapshots
+            .iter()
+            .rposition(|snapshot| snapshot.delta.is_none())
+    }
+}
+
+/// Addresses and values of every byte in `memory` that differs from `keyframe`.
+fn diff(keyframe: &[u8], memory: &[u8]) -> Vec<(u16, u8)> {
+    keyframe
+        .iter()
+        .zip(memory)
+        .enumerate()
+        .filter(|(_, (old, new))| old != new)
+        .map(|(address, (_, new))| (address as u16, *new))
+        .collect()
+}
+
+/// Turns a delta against `old` into one against `new`, where `new` is `old` patched with
+/// `promoted`, without comparing all of memory.
+fn rebase(old: &[u8], new: &[u8], promoted: &[(u16, u8)], delta: &[(u16, u8)]) -> Vec<(u16, u8)> {
+    let mut rebased: Vec<_> = delta
+        .iter()
+        .filter(|(address, value)| new[*address as usize] != *value)
+        .copied()
+        .collect();
+
+    // Bytes the promoted frame changed, which have since gone back to their old value.
+    rebased.extend(
+        promoted
+            .iter()
+            .filter(|(address, _)| {
+                delta
+                    .binary_search_by_key(address, |(address, _)| *address)
+                    .is_err()
+            })
+            .map(|(address, _)| (*address, old[*address as usize])),
+    );
+    rebased.sort_unstable();
+    rebased
+}
+
+fn patch(keyframe: &[u8], delta: &[(u16, u8)]) -> Vec<u8> {
+    let mut memory = keyframe.to_vec();
+    for (address, value) in delta {
+        memory[*address as usize] = *value;
+    }
+    memory
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::system::System;
+
+    /// Counts up in memory at 0x300 with BCD, one step per recorded frame, so digits keep
+    /// returning to earlier values.
+    fn record(frames: usize, capacity: usize) -> (RewindBuffer, Vec<SaveState>) {
+        let mut system = System::default();
+        system
+            .load_rom(&[0xA3, 0x00, 0xF0, 0x33, 0x70, 0x01, 0x12, 0x02])
+            .unwrap();
+        system.step().unwrap();
+
+        let mut buffer = RewindBuffer::new(capacity);
+        let mut states = Vec::new();
+        for _ in 0..frames {
+            for _ in 0..3 {
+                system.step().unwrap();
+            }
+            states.push(system.save_state());
+            buffer.push(system.save_state());
+        }
+        (buffer, states)
+    }
+
+    #[test]
+    fn test_pops_frames_newest_first() {
+        let (mut buffer, mut states) = record(150, 1000);
+        assert_eq!(buffer.len(), 150);
+
+        while let Some(state) = buffer.pop() {
+            assert_eq!(Some(state), states.pop());
+        }
+        assert!(states.is_empty());
+    }
+
+    #[test]
+    fn test_forgets_oldest_frames() {
+        let (mut buffer, states) = record(200, 90);
+        assert_eq!(buffer.len(), 90);
+        assert!(
+            buffer
+                .snapshots
+                .iter()
+                .filter(|s| s.delta.is_some())
+                .count()
+                > 80
+        );
+
+        for state in states.iter().rev().take(90) {
+            assert_eq!(buffer.pop().as_ref(), Some(state));
+        }
+        assert_eq!(buffer.pop(), None);
+    }
+
+    #[test]
+    fn test_disabled_with_no_capacity() {
+        let (mut buffer, _) = record(5, 0);
+        assert!(buffer.is_empty());
+        assert_eq!(buffer.pop(), None);
+    }
+}
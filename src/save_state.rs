@@ -0,0 +1,386 @@
+use std::io::{Read, Write};
+
+use crate::config::Variant;
+use crate::constants::{
+    AUDIO_PATTERN_SIZE, DISPLAY_HEIGHT, DISPLAY_WIDTH, FLAG_COUNT, HIRES_DISPLAY_HEIGHT,
+    HIRES_DISPLAY_WIDTH, KEY_COUNT,
+};
+use crate::error::SaveStateError;
+use crate::framebuffer::Framebuffer;
+use crate::input_output::Keypad;
+use crate::quirks::Quirks;
+
+/// Bytes every save state starts with.
+const MAGIC: &[u8; 4] = b"CH8S";
+
+/// Version of the format written by this build.
+///
+/// Adding chunks, or fields at the end of a chunk, does not need a new version, as older
+/// readers skip what they do not know. Only changes that break older readers bump it.
+pub const FORMAT_VERSION: u16 = 1;
+
+const MACHINE: [u8; 4] = *b"MACH";
+const CPU: [u8; 4] = *b"CPU ";
+const STACK: [u8; 4] = *b"STCK";
+const MEMORY: [u8; 4] = *b"MEM ";
+const DISPLAY: [u8; 4] = *b"DISP";
+const KEYPAD: [u8; 4] = *b"KEYS";
+const QUIRKS: [u8; 4] = *b"QURK";
+const FLAGS: [u8; 4] = *b"FLAG";
+const AUDIO: [u8; 4] = *b"AUDI";
+
+/// Snapshot of everything needed to resume a System where it left off.
+///
+/// On disk a state is the magic bytes and format version, followed by tagged chunks of
+/// the form `tag: [u8; 4], length: u32, payload`, with all numbers little endian.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SaveState {
+    pub(crate) variant: Variant,
+    pub(crate) quirks: Quirks,
+    pub(crate) program_counter: u16,
+    pub(crate) index: u16,
+    pub(crate) register: [u8; 16],
+    pub(crate) delay_timer: u8,
+    pub(crate) sound_timer: u8,
+    pub(crate) awaited_key: Option<u8>,
+    pub(crate) stack: Vec<u16>,
+    pub(crate) stack_pointer: u8,
+    pub(crate) memory: Vec<u8>,
+    pub(crate) framebuffer: Framebuffer,
+    pub(crate) keypad: Keypad,
+    pub(crate) flags: [u8; FLAG_COUNT],
+    pub(crate) planes: u8,
+    pub(crate) audio_pattern: [u8; AUDIO_PATTERN_SIZE],
+    pub(crate) pitch: u8,
+}
+
+impl SaveState {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend(FORMAT_VERSION.to_le_bytes());
+
+        let variant = match self.variant {
+            Variant::Chip8 => 0,
+            Variant::SuperChip => 1,
+            Variant::XoChip => 2,
+        };
+        write_chunk(&mut bytes, MACHINE, &[variant]);
+
+        let mut cpu = Vec::new();
+        cpu.extend(self.program_counter.to_le_bytes());
+        cpu.extend(self.index.to_le_bytes());
+        cpu.extend(self.register);
+        cpu.extend([self.delay_timer, self.sound_timer]);
+        cpu.push(self.awaited_key.unwrap_or(0xFF));
+        write_chunk(&mut bytes, CPU, &cpu);
+
+        let mut stack = vec![self.stack_pointer];
+        stack.extend(self.stack.iter().flat_map(|address| address.to_le_bytes()));
+        write_chunk(&mut bytes, STACK, &stack);
+
+        write_chunk(&mut bytes, MEMORY, &self.memory);
+
+        let mut display = Vec::new();
+        display.extend((self.framebuffer.width() as u16).to_le_bytes());
+        display.extend((self.framebuffer.height() as u16).to_le_bytes());
+        display.extend(self.framebuffer.pixels());
+        write_chunk(&mut bytes, DISPLAY, &display);
+
+        let keys = (0..KEY_COUNT as u8)
+            .filter(|key| self.keypad.is_pressed(*key))
+            .fold(0u16, |keys, key| keys | 1 << key);
+        write_chunk(&mut bytes, KEYPAD, &keys.to_le_bytes());
+
+        let quirks = [
+            self.quirks.vf_reset,
+            self.quirks.memory_increment,
+            self.quirks.shift_in_place,
+            self.quirks.jump_with_vx,
+            self.quirks.sprite_wrap,
+            self.quirks.display_wait,
+        ];
+        write_chunk(&mut bytes, QUIRKS, &quirks.map(u8::from));
+
+        write_chunk(&mut bytes, FLAGS, &self.flags);
+
+        let mut audio = vec![self.planes, self.pitch];
+        audio.extend(self.audio_pattern);
+        write_chunk(&mut bytes, AUDIO, &audio);
+
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, SaveStateError> {
+        if bytes.get(..MAGIC.len()) != Some(MAGIC) {
+            return Err(SaveStateError::NotASaveState);
+        }
+        let mut header = Chunk {
+            tag: *MAGIC,
+            data: &bytes[MAGIC.len()..],
+        };
+        let version = header.u16()?;
+        if version > FORMAT_VERSION {
+            return Err(SaveStateError::UnsupportedVersion { version });
+        }
+
+        let chunks = split_chunks(header.data)?;
+        let find = |tag: [u8; 4]| {
+            chunks
+                .iter()
+                .find(|chunk| chunk.tag == tag)
+                .cloned()
+                .ok_or(SaveStateError::MissingChunk { tag })
+        };
+
+        let mut machine = find(MACHINE)?;
+        let variant = match machine.u8()? {
+            0 => Variant::Chip8,
+            1 => Variant::SuperChip,
+            2 => Variant::XoChip,
+            _ => return Err(machine.malformed()),
+        };
+
+        let mut cpu = find(CPU)?;
+        let program_counter = cpu.u16()?;
+        let index = cpu.u16()?;
+        let register = cpu.array()?;
+        let delay_timer = cpu.u8()?;
+        let sound_timer = cpu.u8()?;
+        let awaited_key = Some(cpu.u8()?).filter(|key| (*key as usize) < KEY_COUNT);
+
+        let mut stack_chunk = find(STACK)?;
+        let stack_pointer = stack_chunk.u8()?;
+        let mut stack = Vec::new();
+        while !stack_chunk.data.is_empty() {
+            stack.push(stack_chunk.u16()?);
+        }
+        if stack_pointer as usize > stack.len() || stack.len() > u8::MAX as usize {
+            return Err(stack_chunk.malformed());
+        }
+
+        let memory_chunk = find(MEMORY)?;
+        if memory_chunk.data.len() != variant.memory_size() {
+            return Err(memory_chunk.malformed());
+        }
+        let memory = memory_chunk.data.to_vec();
+
+        let mut display = find(DISPLAY)?;
+        let size = (display.u16()? as usize, display.u16()? as usize);
+        if size != (DISPLAY_WIDTH, DISPLAY_HEIGHT)
+            && size != (HIRES_DISPLAY_WIDTH, HIRES_DISPLAY_HEIGHT)
+        {
+            return Err(display.malformed());
+        }
+        let framebuffer = Framebuffer::from_pixels(size.0, size.1, display.data.to_vec())
+            .ok_or_else(|| display.malformed())?;
+
+        let keys = find(KEYPAD)?.u16()?;
+        let mut keypad = Keypad::default();
+        for key in 0..KEY_COUNT as u8 {
+            keypad.set(key, keys & 1 << key > 0);
+        }
+
+        let mut quirks_chunk = find(QUIRKS)?;
+        let quirks = Quirks {
+            vf_reset: quirks_chunk.bool()?,
+            memory_increment: quirks_chunk.bool()?,
+            shift_in_place: quirks_chunk.bool()?,
+            jump_with_vx: quirks_chunk.bool()?,
+            sprite_wrap: quirks_chunk.bool()?,
+            display_wait: quirks_chunk.bool()?,
+        };
+
+        let flags = find(FLAGS)?.array()?;
+
+        let mut audio = find(AUDIO)?;
+        let planes = audio.u8()? & 0x3;
+        let pitch = audio.u8()?;
+        let audio_pattern = audio.array()?;
+
+        Ok(SaveState {
+            variant,
+            quirks,
+            program_counter,
+            index,
+            register,
+            delay_timer,
+            sound_timer,
+            awaited_key,
+            stack,
+            stack_pointer,
+            memory,
+            framebuffer,
+            keypad,
+            flags,
+            planes,
+            audio_pattern,
+            pitch,
+        })
+    }
+
+    pub fn write_to<W: Write>(&self, mut writer: W) -> Result<(), SaveStateError> {
+        writer.write_all(&self.to_bytes())?;
+        Ok(())
+    }
+
+    pub fn read_from<R: Read>(mut reader: R) -> Result<Self, SaveStateError> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        SaveState::from_bytes(&bytes)
+    }
+}
+
+fn write_chunk(bytes: &mut Vec<u8>, tag: [u8; 4], payload: &[u8]) {
+    bytes.extend(tag);
+    bytes.extend((payload.len() as u32).to_le_bytes());
+    bytes.extend(payload);
+}
+
+fn split_chunks(mut data: &[u8]) -> Result<Vec<Chunk<'_>>, SaveStateError> {
+    let mut chunks = Vec::new();
+
+    while !data.is_empty() {
+        let mut header = Chunk { tag: *MAGIC, data };
+        let tag = header.array()?;
+        header.tag = tag;
+        let len = header.u32()? as usize;
+        if header.data.len() < len {
+            return Err(header.malformed());
+        }
+
+        chunks.push(Chunk {
+            tag,
+            data: &header.data[..len],
+        });
+        data = &header.data[len..];
+    }
+
+    Ok(chunks)
+}
+
+/// Payload of a single chunk, read from the front.
+///
+/// Reading past the end is an error, but anything left over is ignored, so fields
+/// added to the end of a chunk by newer builds do not stop older ones from loading it.
+#[derive(Clone)]
+struct Chunk<'a> {
+    tag: [u8; 4],
+    data: &'a [u8],
+}
+
+impl<'a> Chunk<'a> {
+    fn malformed(&self) -> SaveStateError {
+        SaveStateError::MalformedChunk { tag: self.tag }
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], SaveStateError> {
+        if self.data.len() < N {
+            return Err(self.malformed());
+        }
+        let (value, rest) = self.data.split_at(N);
+        self.data = rest;
+        Ok(value.try_into().unwrap())
+    }
+
+    fn u8(&mut self) -> Result<u8, SaveStateError> {
+        self.array::<1>().map(|[value]| value)
+    }
+
+    fn bool(&mut self) -> Result<bool, SaveStateError> {
+        self.u8().map(|value| value != 0)
+    }
+
+    fn u16(&mut self) -> Result<u16, SaveStateError> {
+        self.array().map(u16::from_le_bytes)
+    }
+
+    fn u32(&mut self) -> Result<u32, SaveStateError> {
+        self.array().map(u32::from_le_bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::system::System;
+
+    fn state() -> SaveState {
+        let mut system = System::default();
+        system
+            .load_rom(&[0x60, 0x2A, 0xA0, 0x50, 0xD0, 0x05, 0x22, 0x00])
+            .unwrap();
+        system.keypad_mut().press(0xB);
+        for _ in 0..4 {
+            system.step().unwrap();
+        }
+        system.save_state()
+    }
+
+    /// Replaces the version number written after the magic bytes.
+    fn with_version(mut bytes: Vec<u8>, version: u16) -> Vec<u8> {
+        bytes[4..6].copy_from_slice(&version.to_le_bytes());
+        bytes
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let state = state();
+        assert_eq!(SaveState::from_bytes(&state.to_bytes()).unwrap(), state);
+    }
+
+    #[test]
+    fn test_unknown_chunks_and_trailing_fields_are_ignored() {
+        let state = state();
+        let mut bytes = state.to_bytes();
+        write_chunk(&mut bytes, *b"NEW ", &[1, 2, 3]);
+        write_chunk(&mut bytes, QUIRKS, &[0; 8]);
+
+        // The first chunk with a tag wins, so the extended quirks chunk is never read.
+        assert_eq!(SaveState::from_bytes(&bytes).unwrap(), state);
+
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend(FORMAT_VERSION.to_le_bytes());
+        for chunk in split_chunks(&state.to_bytes()[6..]).unwrap() {
+            let mut payload = chunk.data.to_vec();
+            if chunk.tag == QUIRKS {
+                payload.push(1);
+            }
+            write_chunk(&mut bytes, chunk.tag, &payload);
+        }
+        assert_eq!(SaveState::from_bytes(&bytes).unwrap(), state);
+    }
+
+    #[test]
+    fn test_rejects_newer_versions() {
+        let bytes = with_version(state().to_bytes(), FORMAT_VERSION + 1);
+        assert!(matches!(
+            SaveState::from_bytes(&bytes),
+            Err(SaveStateError::UnsupportedVersion { version }) if version == FORMAT_VERSION + 1
+        ));
+    }
+
+    #[test]
+    fn test_rejects_other_files() {
+        assert!(matches!(
+            SaveState::from_bytes(&[0x60, 0x2A, 0xA0, 0x50]),
+            Err(SaveStateError::NotASaveState)
+        ));
+    }
+
+    #[test]
+    fn test_rejects_missing_and_truncated_chunks() {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend(FORMAT_VERSION.to_le_bytes());
+        write_chunk(&mut bytes, MACHINE, &[0]);
+        assert!(matches!(
+            SaveState::from_bytes(&bytes),
+            Err(SaveStateError::MissingChunk { tag: CPU })
+        ));
+
+        let bytes = state().to_bytes();
+        assert!(matches!(
+            SaveState::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(SaveStateError::MalformedChunk { tag: AUDIO })
+        ));
+    }
+}
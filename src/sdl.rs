@@ -0,0 +1,276 @@
+use sdl2::audio::{AudioCallback, AudioDevice, AudioSpecDesired};
+use sdl2::event::Event;
+use sdl2::keyboard::{Keycode, Mod};
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::WindowCanvas;
+use sdl2::EventPump;
+
+use crate::constants::{AUDIO_PATTERN_SIZE, DISPLAY_HEIGHT, DISPLAY_WIDTH};
+use crate::framebuffer::Framebuffer;
+use crate::input_output::{Audio, Display, Input, Keypad};
+
+/// Tone played while the sound timer is active.
+///
+/// This is a square wave, until an XO-CHIP program provides its own audio pattern.
+struct Buzzer {
+    sample_rate: f32,
+    phase: f32,
+    volume: f32,
+    pattern: Option<([u8; AUDIO_PATTERN_SIZE], f32)>,
+}
+
+impl AudioCallback for Buzzer {
+    type Channel = f32;
+
+    fn callback(&mut self, out: &mut [f32]) {
+        for x in out.iter_mut() {
+            let high = match &self.pattern {
+                Some((pattern, rate)) => {
+                    let bit = self.phase as usize;
+                    self.phase = (self.phase + rate / self.sample_rate) % 128.0;
+                    pattern[bit / 8] & (0x80 >> (bit % 8)) > 0
+                }
+                None => {
+                    let high = self.phase <= 0.5;
+                    self.phase = (self.phase + 440.0 / self.sample_rate) % 1.0;
+                    high
+                }
+            };
+
+            *x = if high { self.volume } else { -self.volume };
+        }
+    }
+}
+
+/// Emulator controls bound to keys outside of the keypad.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Hotkey {
+    /// Shift and F1 to F9 save the machine into the numbered slot.
+    SaveState(u8),
+
+    /// F1 to F9 restore the machine from the numbered slot.
+    LoadState(u8),
+
+    /// F12 stops the program and enters the debugger.
+    Break,
+}
+
+/// Window, keyboard and speaker backed by SDL2.
+pub struct SdlInputOutput {
+    width: u32,
+    canvas: WindowCanvas,
+    palette: [Color; 4],
+    events: EventPump,
+    audio: AudioDevice<Buzzer>,
+    hotkeys: Vec<Hotkey>,
+    rewinding: bool,
+    quit: bool,
+}
+
+impl SdlInputOutput {
+    pub fn new() -> Result<Self, String> {
+        let scale = 8;
+        let sdl_context = sdl2::init()?;
+        let video_subsystem = sdl_context.video()?;
+        let audio_subsystem = sdl_context.audio()?;
+
+        let window = video_subsystem
+            .window(
+                "Chip8",
+                scale * DISPLAY_WIDTH as u32,
+                scale * DISPLAY_HEIGHT as u32,
+            )
+            .opengl()
+            .build()
+            .map_err(|e| e.to_string())?;
+
+        let canvas = window.into_canvas().build().map_err(|e| e.to_string())?;
+
+        let desired_spec = AudioSpecDesired {
+            freq: Some(44100),
+            channels: Some(1),
+            samples: None,
+        };
+        let audio = audio_subsystem.open_playback(None, &desired_spec, |spec| Buzzer {
+            sample_rate: spec.freq as f32,
+            phase: 0.0,
+            volume: 0.25,
+            pattern: None,
+        })?;
+
+        Ok(SdlInputOutput {
+            width: scale * DISPLAY_WIDTH as u32,
+            canvas,
+            events: sdl_context.event_pump()?,
+            audio,
+            // Background, first plane, second plane, and both planes.
+            palette: [
+                Color::RGB(20, 20, 0),
+                Color::RGB(150, 150, 35),
+                Color::RGB(200, 90, 20),
+                Color::RGB(90, 40, 10),
+            ],
+            hotkeys: Vec::new(),
+            rewinding: false,
+            quit: false,
+        })
+    }
+
+    /// Whether the window was closed or Escape pressed.
+    pub fn quit_requested(&self) -> bool {
+        self.quit
+    }
+
+    /// Whether Backspace is held down, to step back through recent frames.
+    pub fn rewinding(&self) -> bool {
+        self.rewinding
+    }
+
+    /// Hotkeys pressed since the last call, oldest first.
+    pub fn take_hotkeys(&mut self) -> Vec<Hotkey> {
+        std::mem::take(&mut self.hotkeys)
+    }
+}
+
+/// Save state slot selected by the function keys F1 to F9.
+fn slot(keycode: Keycode) -> Option<u8> {
+    match keycode {
+        Keycode::F1 => Some(1),
+        Keycode::F2 => Some(2),
+        Keycode::F3 => Some(3),
+        Keycode::F4 => Some(4),
+        Keycode::F5 => Some(5),
+        Keycode::F6 => Some(6),
+        Keycode::F7 => Some(7),
+        Keycode::F8 => Some(8),
+        Keycode::F9 => Some(9),
+        _ => None,
+    }
+}
+
+/// Maps the left hand side of a QWERTY keyboard onto the hexadecimal keypad.
+///
+/// ```text
+/// 1 2 3 4        1 2 3 C
+/// Q W E R   ->   4 5 6 D
+/// A S D F        7 8 9 E
+/// Z X C V        A 0 B F
+/// ```
+fn keymap(keycode: Keycode) -> Option<u8> {
+    match keycode {
+        Keycode::Num1 => Some(0x1),
+        Keycode::Num2 => Some(0x2),
+        Keycode::Num3 => Some(0x3),
+        Keycode::Num4 => Some(0xC),
+        Keycode::Q => Some(0x4),
+        Keycode::W => Some(0x5),
+        Keycode::E => Some(0x6),
+        Keycode::R => Some(0xD),
+        Keycode::A => Some(0x7),
+        Keycode::S => Some(0x8),
+        Keycode::D => Some(0x9),
+        Keycode::F => Some(0xE),
+        Keycode::Z => Some(0xA),
+        Keycode::X => Some(0x0),
+        Keycode::C => Some(0xB),
+        Keycode::V => Some(0xF),
+        _ => None,
+    }
+}
+
+impl Display for SdlInputOutput {
+    fn present(&mut self, framebuffer: &Framebuffer) {
+        self.canvas.set_draw_color(self.palette[0]);
+        self.canvas.clear();
+
+        // The window keeps its size, so high resolution pixels are drawn smaller.
+        let scale = self.width / framebuffer.width() as u32;
+
+        for (i, pixel) in framebuffer.pixels().iter().enumerate() {
+            if *pixel > 0 {
+                let x = (i % framebuffer.width()) as u32;
+                let y = (i / framebuffer.width()) as u32;
+
+                let rect = Rect::from(((scale * x) as i32, (scale * y) as i32, scale, scale));
+
+                self.canvas
+                    .set_draw_color(self.palette[(*pixel & 0x3) as usize]);
+                self.canvas.fill_rect(rect).expect("failed to draw pixel");
+            }
+        }
+
+        self.canvas.present()
+    }
+}
+
+impl Input for SdlInputOutput {
+    fn poll(&mut self, keypad: &mut Keypad) {
+        for event in self.events.poll_iter() {
+            match event {
+                Event::Quit { .. }
+                | Event::KeyDown {
+                    keycode: Some(Keycode::Escape),
+                    ..
+                } => self.quit = true,
+                Event::KeyDown {
+                    keycode: Some(Keycode::Backspace),
+                    ..
+                } => self.rewinding = true,
+                Event::KeyUp {
+                    keycode: Some(Keycode::Backspace),
+                    ..
+                } => self.rewinding = false,
+                Event::KeyDown {
+                    keycode: Some(keycode),
+                    keymod,
+                    repeat,
+                    ..
+                } => {
+                    if let Some(key) = keymap(keycode) {
+                        keypad.press(key);
+                    }
+                    match slot(keycode) {
+                        Some(slot)
+                            if !repeat && keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD) =>
+                        {
+                            self.hotkeys.push(Hotkey::SaveState(slot))
+                        }
+                        Some(slot) if !repeat => self.hotkeys.push(Hotkey::LoadState(slot)),
+                        None if keycode == Keycode::F12 && !repeat => {
+                            self.hotkeys.push(Hotkey::Break)
+                        }
+                        _ => {}
+                    }
+                }
+                Event::KeyUp {
+                    keycode: Some(keycode),
+                    ..
+                } => {
+                    if let Some(key) = keymap(keycode) {
+                        keypad.release(key);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+impl Audio for SdlInputOutput {
+    fn set_beep(&mut self, active: bool) {
+        if active {
+            self.audio.resume();
+        } else {
+            self.audio.pause();
+        }
+    }
+
+    fn set_pattern(&mut self, pattern: Option<&[u8; AUDIO_PATTERN_SIZE]>, pitch: u8) {
+        let rate = 4000.0 * 2f32.powf((pitch as f32 - 64.0) / 48.0);
+        let mut buzzer = self.audio.lock();
+
+        buzzer.pattern = pattern.map(|pattern| (*pattern, rate));
+        buzzer.phase %= 128.0;
+    }
+}
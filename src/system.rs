@@ -1,70 +1,607 @@
+use std::collections::BTreeSet;
+use std::fs::File;
+use std::io::Read;
+use std::ops::Range;
 use std::path::Path;
 use std::time::Duration;
-use std::{fs, thread};
 
-use sdl2::event::EventPollIterator;
-use sdl2::libc::{sleep, time};
-
-use crate::input_output::InputOutput;
+use crate::config::{Config, Timing};
+use crate::constants::{
+    AUDIO_PATTERN_SIZE, BIG_FONT_CHARACTER_SIZE, BIG_FONT_SET, DEFAULT_PITCH, FLAG_COUNT,
+    FONT_CHARACTER_SIZE, FONT_SET, HIRES_DISPLAY_HEIGHT, HIRES_DISPLAY_WIDTH, TIMER_FREQUENCY,
+};
+use crate::error::{ConfigError, ExecutionError, RomError, SaveStateError};
+use crate::framebuffer::Framebuffer;
+use crate::input_output::{Headless, InputOutput, Keypad};
 use crate::opcode::{decode, Operation};
+use crate::profile::Profiler;
+use crate::quirks::Quirks;
+use crate::random::{RandomSource, XorShift};
+use crate::rewind::RewindBuffer;
+use crate::save_state::SaveState;
+use crate::timing::{vip_cycles, VIP_SKIP_CYCLES};
+use crate::trace::{TraceEntry, Tracer};
+use crate::watchpoint::{Access, Register, WatchHit, WatchTarget, Watchpoint};
+
+/// What happened when the System executed an instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepOutcome {
+    /// The instruction ran to completion.
+    Executed,
+
+    /// The instruction is waiting on a key press or the display, and will be executed again.
+    Waiting,
+
+    /// The program asked the interpreter to exit.
+    Exited,
+
+    /// Execution stopped at a breakpoint, before running the instruction there.
+    Breakpoint,
+
+    /// Execution stopped after an instruction touched a watchpoint.
+    Watchpoint,
+}
 
-pub struct System {
+pub struct System<IO: InputOutput = Headless> {
     pub draw_flag: bool,
+    config: Config,
     ops: u64,
+    cycles: u64,
+    cycle_budget: i64,
     program_counter: u16,
     index: u16,
-    memory: [u8; 4096],
+    memory: Vec<u8>,
     register: [u8; 16],
-    stack: [u16; 8],
+    stack: Vec<u16>,
     stack_pointer: u8,
-    io: InputOutput,
+    delay_timer: u8,
+    sound_timer: u8,
+    timer_elapsed: Duration,
+    vertical_blank: bool,
+    framebuffer: Framebuffer,
+    keypad: Keypad,
+    flags: [u8; FLAG_COUNT],
+    planes: u8,
+    audio_pattern: [u8; AUDIO_PATTERN_SIZE],
+    pitch: u8,
+    awaited_key: Option<u8>,
+    random: Box<dyn RandomSource>,
+    rewind: RewindBuffer,
+    breakpoints: BTreeSet<u16>,
+    /// Breakpoint execution is paused at, which is run past rather than stopped at again
+    /// once running resumes.
+    paused_at: Option<u16>,
+    watchpoints: Vec<Watchpoint>,
+    /// Memory the current instruction accessed, with what was there before for writes.
+    accesses: Vec<(Range<usize>, Access, Vec<u8>)>,
+    watch_hits: Vec<WatchHit>,
+    tracer: Option<Box<dyn Tracer>>,
+    profiler: Option<Profiler>,
+    io: IO,
 }
 
-impl Default for System {
+impl Default for System<Headless> {
     fn default() -> Self {
-        System {
+        System::new(Headless::default())
+    }
+}
+
+impl<IO: InputOutput> System<IO> {
+    pub fn new(io: IO) -> Self {
+        System::build(io, Config::default())
+    }
+
+    /// Creates a System configured by `config`, as long as its fonts fit in memory.
+    pub fn with_config(io: IO, config: Config) -> Result<Self, ConfigError> {
+        config.validate()?;
+        Ok(System::build(io, config))
+    }
+
+    /// Creates a System from a Config that has been validated.
+    fn build(io: IO, config: Config) -> Self {
+        let mut system = System {
             draw_flag: false,
             ops: 0,
-            program_counter: 0x200,
+            cycles: 0,
+            cycle_budget: 0,
+            program_counter: config.load_address,
             index: 0,
-            memory: [0; 4096],
+            memory: vec![0; config.variant.memory_size()],
             register: [0; 16],
-            stack: [0; 8],
+            stack: vec![0; config.stack_depth as usize],
             stack_pointer: 0,
-            io: InputOutput::default(),
+            delay_timer: 0,
+            sound_timer: 0,
+            timer_elapsed: Duration::ZERO,
+            vertical_blank: false,
+            framebuffer: Framebuffer::default(),
+            keypad: Keypad::default(),
+            flags: [0; FLAG_COUNT],
+            planes: 1,
+            audio_pattern: [0; AUDIO_PATTERN_SIZE],
+            pitch: DEFAULT_PITCH,
+            awaited_key: None,
+            random: random_source(config.seed),
+            rewind: RewindBuffer::default(),
+            breakpoints: BTreeSet::new(),
+            paused_at: None,
+            watchpoints: Vec::new(),
+            accesses: Vec::new(),
+            watch_hits: Vec::new(),
+            tracer: None,
+            profiler: None,
+            config,
+            io,
+        };
+        system.load_font();
+        system
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    pub fn quirks(&self) -> &Quirks {
+        &self.config.quirks
+    }
+
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.config.quirks = quirks;
+    }
+
+    /// Replaces the generator CXNN draws random numbers from.
+    pub fn set_random_source(&mut self, random: Box<dyn RandomSource>) {
+        self.random = random;
+    }
+
+    /// Returns the machine to its power-on state.
+    ///
+    /// Memory is wiped, so the ROM has to be loaded again afterwards.
+    /// Like on the HP-48, the RPL user flags survive a reset.
+    /// Random numbers start over from the configured seed.
+    pub fn reset(&mut self) {
+        self.draw_flag = false;
+        self.ops = 0;
+        self.cycles = 0;
+        self.cycle_budget = 0;
+        self.program_counter = self.config.load_address;
+        self.index = 0;
+        self.memory = vec![0; self.config.variant.memory_size()];
+        self.register = [0; 16];
+        self.stack = vec![0; self.config.stack_depth as usize];
+        self.stack_pointer = 0;
+        self.delay_timer = 0;
+        self.sound_timer = 0;
+        self.timer_elapsed = Duration::ZERO;
+        self.vertical_blank = false;
+        self.framebuffer = Framebuffer::default();
+        self.keypad = Keypad::default();
+        self.planes = 1;
+        self.audio_pattern = [0; AUDIO_PATTERN_SIZE];
+        self.pitch = DEFAULT_PITCH;
+        self.awaited_key = None;
+        self.paused_at = None;
+        self.watch_hits.clear();
+        self.rewind.clear();
+        self.random = random_source(self.config.seed);
+        self.load_font();
+    }
+
+    fn load_font(&mut self) {
+        let start = self.config.font_address as usize;
+        self.memory[start..start + FONT_SET.len()].copy_from_slice(&FONT_SET);
+
+        let start = self.config.big_font_address as usize;
+        self.memory[start..start + BIG_FONT_SET.len()].copy_from_slice(&BIG_FONT_SET);
+    }
+
+    /// Copies a ROM image into memory at the configured load address.
+    pub fn load_rom(&mut self, data: &[u8]) -> Result<(), RomError> {
+        let start = self.config.load_address as usize;
+        let capacity = self.memory.len().saturating_sub(start);
+
+        if data.is_empty() {
+            return Err(RomError::Empty);
+        }
+        if data.len() > capacity {
+            return Err(RomError::TooLarge {
+                size: data.len(),
+                capacity,
+            });
         }
+
+        self.memory[start..start + data.len()].copy_from_slice(data);
+        Ok(())
+    }
+
+    pub fn load_rom_from_reader<R: Read>(&mut self, reader: R) -> Result<(), RomError> {
+        let capacity = self
+            .memory
+            .len()
+            .saturating_sub(self.config.load_address as usize);
+        let mut data = Vec::new();
+
+        // Read one byte more than fits, so oversized ROMs are still reported as such.
+        reader.take(capacity as u64 + 1).read_to_end(&mut data)?;
+        self.load_rom(&data)
+    }
+
+    pub fn load_rom_from_file<P: AsRef<Path>>(&mut self, filepath: P) -> Result<(), RomError> {
+        self.load_rom_from_reader(File::open(filepath)?)
     }
-}
 
-impl System {
-    pub fn load_rom(&mut self, data: Vec<u8>) {
-        for (i, d) in data.iter().enumerate() {
-            self.memory[i + 0x200] = *d;
+    /// Captures the machine, so it can be restored with `load_state` later.
+    pub fn save_state(&self) -> SaveState {
+        SaveState {
+            variant: self.config.variant,
+            quirks: self.config.quirks,
+            program_counter: self.program_counter,
+            index: self.index,
+            register: self.register,
+            delay_timer: self.delay_timer,
+            sound_timer: self.sound_timer,
+            awaited_key: self.awaited_key,
+            stack: self.stack.clone(),
+            stack_pointer: self.stack_pointer,
+            memory: self.memory.clone(),
+            framebuffer: self.framebuffer.clone(),
+            keypad: self.keypad,
+            flags: self.flags,
+            planes: self.planes,
+            audio_pattern: self.audio_pattern,
+            pitch: self.pitch,
         }
     }
 
-    pub fn load_rom_from_file<P: AsRef<Path>>(&mut self, filepath: P) {
-        let data = fs::read(filepath).unwrap();
-        self.load_rom(data)
+    /// Puts the machine back into a previously saved state.
+    ///
+    /// The variant, stack depth and quirks are taken from the state, as the program
+    /// may not run correctly with any others.
+    pub fn load_state(&mut self, state: &SaveState) {
+        self.config.variant = state.variant;
+        self.config.stack_depth = state.stack.len() as u8;
+        self.config.quirks = state.quirks;
+        self.program_counter = state.program_counter;
+        self.index = state.index;
+        self.register = state.register;
+        self.delay_timer = state.delay_timer;
+        self.sound_timer = state.sound_timer;
+        self.awaited_key = state.awaited_key;
+        self.stack = state.stack.clone();
+        self.stack_pointer = state.stack_pointer;
+        self.memory = state.memory.clone();
+        self.framebuffer = state.framebuffer.clone();
+        self.keypad = state.keypad;
+        self.flags = state.flags;
+        self.planes = state.planes;
+        self.audio_pattern = state.audio_pattern;
+        self.pitch = state.pitch;
+        self.timer_elapsed = Duration::ZERO;
+        self.vertical_blank = false;
+        self.draw_flag = true;
+
+        self.io.set_beep(self.sound_active());
+        // States saved before a program set a pattern play the buzzer's own tone.
+        let pattern = self.audio_pattern != [0; AUDIO_PATTERN_SIZE];
+        self.io
+            .set_pattern(pattern.then_some(&self.audio_pattern), self.pitch);
+    }
+
+    /// Starts recording the last `frames` frames, so they can be stepped back through with
+    /// `rewind_frame`. A frame is recorded every time the timers tick. Zero turns it off.
+    pub fn set_rewind_capacity(&mut self, frames: usize) {
+        self.rewind = RewindBuffer::new(frames);
+    }
+
+    /// Number of frames that can currently be rewound.
+    pub fn rewind_len(&self) -> usize {
+        self.rewind.len()
+    }
+
+    /// Goes back to the most recently recorded frame, returning false once the history
+    /// has run out.
+    pub fn rewind_frame(&mut self) -> bool {
+        match self.rewind.pop() {
+            Some(state) => {
+                self.load_state(&state);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn save_state_to_file<P: AsRef<Path>>(&self, filepath: P) -> Result<(), SaveStateError> {
+        self.save_state().write_to(File::create(filepath)?)
+    }
+
+    pub fn load_state_from_file<P: AsRef<Path>>(
+        &mut self,
+        filepath: P,
+    ) -> Result<(), SaveStateError> {
+        let state = SaveState::read_from(File::open(filepath)?)?;
+        self.load_state(&state);
+        Ok(())
+    }
+
+    pub fn io(&self) -> &IO {
+        &self.io
+    }
+
+    pub fn io_mut(&mut self) -> &mut IO {
+        &mut self.io
+    }
+
+    pub fn framebuffer(&self) -> &Framebuffer {
+        &self.framebuffer
+    }
+
+    pub fn keypad(&self) -> &Keypad {
+        &self.keypad
+    }
+
+    pub fn keypad_mut(&mut self) -> &mut Keypad {
+        &mut self.keypad
+    }
+
+    pub fn program_counter(&self) -> u16 {
+        self.program_counter
+    }
+
+    pub fn set_program_counter(&mut self, address: u16) {
+        self.program_counter = address;
+    }
+
+    pub fn index(&self) -> u16 {
+        self.index
+    }
+
+    pub fn set_index(&mut self, index: u16) {
+        self.index = index;
+    }
+
+    /// V0 to VF.
+    pub fn registers(&self) -> &[u8; 16] {
+        &self.register
+    }
+
+    pub fn registers_mut(&mut self) -> &mut [u8; 16] {
+        &mut self.register
+    }
+
+    /// Return addresses of the subroutines currently called, innermost last.
+    pub fn stack(&self) -> &[u16] {
+        &self.stack[..self.stack_pointer as usize]
+    }
+
+    pub fn stack_pointer(&self) -> u8 {
+        self.stack_pointer
+    }
+
+    pub fn memory(&self) -> &[u8] {
+        &self.memory
+    }
+
+    pub fn memory_mut(&mut self) -> &mut [u8] {
+        &mut self.memory
+    }
+
+    /// Makes `run_cycles` stop before executing the instruction at `address`.
+    pub fn add_breakpoint(&mut self, address: u16) {
+        self.breakpoints.insert(address);
+    }
+
+    /// Returns whether there was a breakpoint at `address`.
+    pub fn remove_breakpoint(&mut self, address: u16) -> bool {
+        self.breakpoints.remove(&address)
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = u16> + '_ {
+        self.breakpoints.iter().copied()
+    }
+
+    /// Makes `step` report accesses to memory or changes to a register, and `run_cycles`
+    /// stop after the instruction that made them.
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) {
+        if !self.watchpoints.contains(&watchpoint) {
+            self.watchpoints.push(watchpoint);
+        }
+    }
+
+    /// Returns whether the watchpoint was set.
+    pub fn remove_watchpoint(&mut self, watchpoint: &Watchpoint) -> bool {
+        let len = self.watchpoints.len();
+        self.watchpoints.retain(|w| w != watchpoint);
+        self.watchpoints.len() != len
+    }
+
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        &self.watchpoints
+    }
+
+    /// Watched memory and registers the last instruction touched, in the order it did so.
+    pub fn watch_hits(&self) -> &[WatchHit] {
+        &self.watch_hits
+    }
+
+    pub fn delay_timer(&self) -> u8 {
+        self.delay_timer
+    }
+
+    pub fn sound_timer(&self) -> u8 {
+        self.sound_timer
+    }
+
+    pub fn set_delay_timer(&mut self, value: u8) {
+        self.delay_timer = value;
     }
 
-    pub fn iter_events(&mut self) -> EventPollIterator {
-        self.io.iter_events()
+    pub fn set_sound_timer(&mut self, value: u8) {
+        self.sound_timer = value;
+        self.io.set_beep(self.sound_active());
     }
 
-    pub fn step(&mut self) {
-        let l = (self.memory[self.program_counter as usize] as u16) << 8;
-        let r = (self.memory[self.program_counter as usize + 1] as u16);
+    /// Whether the buzzer should currently be sounding.
+    pub fn sound_active(&self) -> bool {
+        self.sound_timer > 0
+    }
+
+    /// Counts both timers down by one, as happens 60 times a second.
+    pub fn tick_timers(&mut self) {
+        self.delay_timer = self.delay_timer.saturating_sub(1);
+        self.sound_timer = self.sound_timer.saturating_sub(1);
+        self.vertical_blank = true;
+        self.io.set_beep(self.sound_active());
+
+        if self.rewind.capacity() > 0 {
+            let state = self.save_state();
+            self.rewind.push(state);
+        }
+    }
+
+    /// Advances the timers by however many 60 Hz ticks fit into `elapsed`.
+    ///
+    /// Leftover time is carried over to the next call, so the timers keep
+    /// their rate independently of how often instructions are executed.
+    pub fn update_timers(&mut self, elapsed: Duration) {
+        let tick = Duration::from_secs(1) / TIMER_FREQUENCY;
+
+        self.timer_elapsed += elapsed;
+        while self.timer_elapsed >= tick {
+            self.timer_elapsed -= tick;
+            self.tick_timers();
+        }
+    }
+
+    /// Lets the input backend update the keypad.
+    pub fn poll_input(&mut self) {
+        self.io.poll(&mut self.keypad);
+    }
+
+    /// Starts passing every executed instruction to `tracer`.
+    pub fn set_tracer(&mut self, tracer: Box<dyn Tracer>) {
+        self.tracer = Some(tracer);
+    }
+
+    /// Stops tracing, handing back the tracer so it can be finished.
+    pub fn take_tracer(&mut self) -> Option<Box<dyn Tracer>> {
+        self.tracer.take()
+    }
+
+    /// Starts counting executed instructions with `profiler`.
+    pub fn set_profiler(&mut self, profiler: Profiler) {
+        self.profiler = Some(profiler);
+    }
+
+    pub fn profiler(&self) -> Option<&Profiler> {
+        self.profiler.as_ref()
+    }
+
+    /// Stops profiling, handing back what was counted.
+    pub fn take_profiler(&mut self) -> Option<Profiler> {
+        self.profiler.take()
+    }
+
+    /// Instructions executed since the last reset.
+    pub fn ops(&self) -> u64 {
+        self.ops
+    }
+
+    /// Fetches, decodes and executes a single instruction.
+    pub fn step(&mut self) -> Result<StepOutcome, ExecutionError> {
+        self.watch_hits.clear();
+        if self.watchpoints.is_empty() && self.tracer.is_none() && self.profiler.is_none() {
+            let outcome = self.execute();
+            return self.count(outcome);
+        }
+
+        let address = self.program_counter;
+        let register = self.register;
+        let index = self.index;
+        let entry = self.trace_entry();
+        let cycles = self.cycles;
+        let outcome = self.execute();
+
+        if let (Some(tracer), Some(entry)) = (&mut self.tracer, &entry) {
+            // Instructions that wait are executed again, and traced then.
+            if outcome != Ok(StepOutcome::Waiting) {
+                tracer.trace(entry);
+            }
+        }
+        if let (Some(profiler), Some(entry)) = (&mut self.profiler, &entry) {
+            if matches!(outcome, Ok(StepOutcome::Executed | StepOutcome::Exited)) {
+                if let Ok(operation) = decode(entry.opcode) {
+                    profiler.record(entry.pc, &operation, self.cycles - cycles);
+                }
+            }
+        }
+        self.check_watchpoints(address, &register, index);
+        self.count(outcome)
+    }
+
+    /// Counts the instruction towards `ops` if it ran to completion.
+    fn count(
+        &mut self,
+        outcome: Result<StepOutcome, ExecutionError>,
+    ) -> Result<StepOutcome, ExecutionError> {
+        if matches!(outcome, Ok(StepOutcome::Executed | StepOutcome::Exited)) {
+            self.ops += 1;
+        }
+        outcome
+    }
+
+    /// The state the instruction at the program counter is about to run from, if there is
+    /// an instruction there.
+    fn trace_entry(&self) -> Option<TraceEntry> {
+        let pc = self.program_counter;
+        let word = self.memory.get(pc as usize..pc as usize + 2)?;
+
+        Some(TraceEntry {
+            ops: self.ops,
+            pc,
+            opcode: u16::from_be_bytes([word[0], word[1]]),
+            registers: self.register,
+            index: self.index,
+            stack_pointer: self.stack_pointer,
+            delay_timer: self.delay_timer,
+            sound_timer: self.sound_timer,
+        })
+    }
+
+    fn execute(&mut self) -> Result<StepOutcome, ExecutionError> {
+        let address = self.program_counter;
+        if address as usize + 1 >= self.memory.len() {
+            return Err(ExecutionError::ProgramCounterOutOfBounds { address });
+        }
+
+        let l = (self.memory[address as usize] as u16) << 8;
+        let r = self.memory[address as usize + 1] as u16;
         let opcode = l | r;
 
-        match decode(opcode).unwrap() {
-            Operation::NoOperation => self.program_counter += 2,
+        let variant = self.config.variant;
+        let operation = decode(opcode)
+            .ok()
+            .filter(|operation| variant.has_super_chip() || !operation.is_super_chip())
+            .filter(|operation| variant.has_xo_chip() || !operation.is_xo_chip())
+            .ok_or(ExecutionError::InvalidOpcode {
+                address,
+                word: opcode,
+            })?;
+        let cost = match self.config.timing {
+            Timing::Instructions => 1,
+            Timing::CosmacVip => vip_cycles(&operation, &self.register),
+        };
+
+        match operation {
+            Operation::NoOperation => self.advance(address, 2)?,
             Operation::ClearDisplay => {
-                self.io.clear();
-                self.program_counter += 2;
+                self.framebuffer.clear_planes(self.planes);
+                self.advance(address, 2)?;
                 self.draw_flag = true;
             }
             Operation::SubroutineReturn => {
+                if self.stack_pointer == 0 {
+                    return Err(ExecutionError::StackUnderflow { address });
+                }
                 self.stack_pointer -= 1;
                 self.program_counter = self.stack[self.stack_pointer as usize];
             }
@@ -72,163 +609,1202 @@ impl System {
                 self.program_counter = nnn;
             }
             Operation::SubroutineCall { nnn } => {
-                self.stack[self.stack_pointer as usize] = self.program_counter;
+                if self.stack_pointer as usize == self.stack.len() {
+                    return Err(ExecutionError::StackOverflow { address });
+                }
+                self.stack[self.stack_pointer as usize] = address
+                    .checked_add(2)
+                    .ok_or(ExecutionError::ProgramCounterOutOfBounds { address })?;
                 self.stack_pointer += 1;
                 self.program_counter = nnn;
             }
             Operation::EqualityCheck { x, nn } => {
                 if self.register[x as usize] == nn {
-                    self.program_counter += 2;
+                    self.skip_next_instruction(address)?;
                 }
-                self.program_counter += 2;
+                self.advance(address, 2)?;
             }
             Operation::InequalityCheck { x, nn } => {
                 if self.register[x as usize] != nn {
-                    self.program_counter += 2;
+                    self.skip_next_instruction(address)?;
                 }
-                self.program_counter += 2;
+                self.advance(address, 2)?;
             }
             Operation::EqualityRegisterCheck { x, y } => {
                 if self.register[x as usize] == self.register[y as usize] {
-                    self.program_counter += 2;
+                    self.skip_next_instruction(address)?;
                 }
-                self.program_counter += 2;
+                self.advance(address, 2)?;
             }
             Operation::SetRegister { x, nn } => {
                 self.register[x as usize] = nn;
-                self.program_counter += 2;
+                self.advance(address, 2)?;
             }
             Operation::AddRegister { x, nn } => {
-                let mut value = self.register[x as usize] as u16;
-                value += nn as u16;
-                self.register[x as usize] = value as u8;
-                self.program_counter += 2;
+                self.register[x as usize] = self.register[x as usize].wrapping_add(nn);
+                self.advance(address, 2)?;
             }
             Operation::SetRegisterFromRegister { x, y } => {
                 self.register[x as usize] = self.register[y as usize];
-                self.program_counter += 2;
+                self.advance(address, 2)?;
             }
             Operation::BitwiseOr { x, y } => {
                 self.register[x as usize] |= self.register[y as usize];
-                self.program_counter += 2;
+                self.reset_flag_after_logic();
+                self.advance(address, 2)?;
             }
             Operation::BitwiseAnd { x, y } => {
                 self.register[x as usize] &= self.register[y as usize];
-                self.program_counter += 2;
+                self.reset_flag_after_logic();
+                self.advance(address, 2)?;
             }
             Operation::BitwiseXor { x, y } => {
                 self.register[x as usize] ^= self.register[y as usize];
-                self.program_counter += 2;
+                self.reset_flag_after_logic();
+                self.advance(address, 2)?;
             }
             Operation::AddValues { x, y } => {
-                let mut value: u16 = self.register[x as usize] as u16;
-                value += self.register[y as usize] as u16;
-
-                if value > 255 {
-                    self.register[0xF] = 1;
-                } else {
-                    self.register[0xF] = 0;
-                }
+                let (value, carry) =
+                    self.register[x as usize].overflowing_add(self.register[y as usize]);
 
-                self.register[x as usize] = value as u8;
-                self.program_counter += 2;
+                self.register[x as usize] = value;
+                self.register[0xF] = carry as u8;
+                self.advance(address, 2)?;
             }
             Operation::SubtractValues { x, y } => {
-                if self.register[x as usize] > self.register[y as usize] {
-                    self.register[0xF] = 1;
-                } else {
-                    self.register[0xF] = 0;
-                }
+                let (value, borrow) =
+                    self.register[x as usize].overflowing_sub(self.register[y as usize]);
 
-                self.register[x as usize] -= self.register[y as usize];
-                self.program_counter += 2;
+                self.register[x as usize] = value;
+                self.register[0xF] = !borrow as u8;
+                self.advance(address, 2)?;
             }
-            Operation::StoreLeastSignificant { x, .. } => {
-                self.register[0xF] = (self.register[x as usize] & 0x1);
-                self.register[x as usize] >>= 1;
+            Operation::StoreLeastSignificant { x, y } => {
+                let value = self.shift_source(x, y);
+                let flag = value & 0x1;
+
+                self.register[x as usize] = value >> 1;
+                self.register[0xF] = flag;
+                self.advance(address, 2)?;
             }
             Operation::SubtractValueFromRegister { x, y } => {
-                if self.register[y as usize] > self.register[x as usize] {
-                    self.register[0xF] = 1;
-                } else {
-                    self.register[0xF] = 0;
-                }
-                self.register[x as usize] = self.register[y as usize] - self.register[x as usize];
+                let (value, borrow) =
+                    self.register[y as usize].overflowing_sub(self.register[x as usize]);
+
+                self.register[x as usize] = value;
+                self.register[0xF] = !borrow as u8;
+                self.advance(address, 2)?;
             }
-            Operation::StoreMostSignificant { x, .. } => {
-                self.register[0xF] = (self.register[x as usize] & 0x80) >> 7;
-                self.register[x as usize] <<= 1;
+            Operation::StoreMostSignificant { x, y } => {
+                let value = self.shift_source(x, y);
+                let flag = (value & 0x80) >> 7;
+
+                self.register[x as usize] = value << 1;
+                self.register[0xF] = flag;
+                self.advance(address, 2)?;
             }
             Operation::InequalityRegisterCheck { x, y } => {
                 if self.register[x as usize] != self.register[y as usize] {
-                    self.program_counter += 2;
+                    self.skip_next_instruction(address)?;
                 }
-                self.program_counter += 2;
+                self.advance(address, 2)?;
             }
             Operation::SetIndexToAddress { nnn } => {
                 self.index = nnn;
-                self.program_counter += 2;
+                self.advance(address, 2)?;
             }
             Operation::GotoAddressWithRegister { nnn } => {
-                self.program_counter = self.register[0] as u16 + nnn;
+                let offset = if self.config.quirks.jump_with_vx {
+                    self.register[(nnn >> 8) as usize]
+                } else {
+                    self.register[0]
+                };
+
+                self.program_counter = offset as u16 + nnn;
+            }
+            Operation::AssignRandomNumber { x, nn } => {
+                self.register[x as usize] = self.random.next_byte() & nn;
+                self.advance(address, 2)?;
             }
             Operation::DrawSprite { x, y, n } => {
-                let x_pos = self.register[x as usize] % 64;
-                let y_pos = self.register[y as usize] % 32;
+                if self.config.quirks.display_wait {
+                    if !self.vertical_blank {
+                        return Ok(StepOutcome::Waiting);
+                    }
+                    self.vertical_blank = false;
+                }
+
+                let width = self.framebuffer.width();
+                let height = self.framebuffer.height();
+                let x_pos = self.register[x as usize] as usize % width;
+                let y_pos = self.register[y as usize] as usize % height;
+
+                let (columns, rows) = if n == 0 && self.config.variant.has_super_chip() {
+                    (16, 16)
+                } else {
+                    (8, n as usize)
+                };
+                let row_size = columns / 8;
+                let plane_count = self.planes.count_ones() as usize;
+                let sprite =
+                    self.read_range(address, self.index as usize, rows * row_size * plane_count)?;
+                let sprite = sprite.start..sprite.start + rows * row_size;
 
                 self.register[0xF] = 0;
 
-                for yline in 0..n {
-                    let pixel = self.memory[(self.index + yline as u16) as usize];
+                // XO-CHIP draws one sprite for each selected plane, one after another in memory.
+                let planes = [1, 2].into_iter().filter(|plane| self.planes & plane > 0);
+                for (i, plane) in planes.enumerate() {
+                    let start = sprite.start + i * sprite.len();
 
-                    for xline in 0..8 {
-                        let sprite_pixel = pixel & (0x80 >> xline);
-                        let screen_pixel = self.io.pixels[((y_pos as usize + yline as usize) * 64
-                            + (x_pos as usize + xline as usize))
-                            as usize];
+                    for yline in 0..rows {
+                        for xline in 0..columns {
+                            let pixel = self.memory[start + yline * row_size + xline / 8];
+                            let sprite_pixel = pixel & (0x80 >> (xline % 8));
+                            let (mut px, mut py) = (x_pos + xline, y_pos + yline);
 
-                        if (sprite_pixel > 0) {
-                            if screen_pixel > 0 {
+                            // The starting position always wraps, but the rest of the sprite is
+                            // only wrapped with the quirk, and clipped at the edges otherwise.
+                            if self.config.quirks.sprite_wrap {
+                                px %= width;
+                                py %= height;
+                            }
+
+                            if sprite_pixel > 0
+                                && px < width
+                                && py < height
+                                && self.framebuffer.toggle(px, py, plane)
+                            {
                                 self.register[0xF] = 1;
                             }
-                            self.io.pixels[((y_pos as usize + yline as usize) * 64
-                                + (x_pos as usize + xline as usize))
-                                as usize] ^= 0xFF;
                         }
                     }
                 }
 
-                let mut data: Vec<u8> = vec![];
+                self.advance(address, 2)?;
+                self.draw_flag = true;
+            }
+            Operation::SkipIfKeyPressed { x } => {
+                if self.keypad.is_pressed(self.register[x as usize]) {
+                    self.skip_next_instruction(address)?;
+                }
+                self.advance(address, 2)?;
+            }
+            Operation::SkipIfKeyNotPressed { x } => {
+                if !self.keypad.is_pressed(self.register[x as usize]) {
+                    self.skip_next_instruction(address)?;
+                }
+                self.advance(address, 2)?;
+            }
+            Operation::GetDelayTimer { x } => {
+                self.register[x as usize] = self.delay_timer;
+                self.advance(address, 2)?;
+            }
+            Operation::StoreNextKeypress { x } => {
+                // Like the COSMAC VIP, the key is only stored once it has been released.
+                match self.awaited_key {
+                    Some(key) if !self.keypad.is_pressed(key) => {
+                        self.register[x as usize] = key;
+                        self.awaited_key = None;
+                        self.advance(address, 2)?;
+                    }
+                    Some(_) => return Ok(StepOutcome::Waiting),
+                    None => {
+                        self.awaited_key = self.keypad.first_pressed();
+                        return Ok(StepOutcome::Waiting);
+                    }
+                }
+            }
+            Operation::SetDelayTimer { x } => {
+                self.delay_timer = self.register[x as usize];
+                self.advance(address, 2)?;
+            }
+            Operation::SetSoundTimer { x } => {
+                self.sound_timer = self.register[x as usize];
+                self.io.set_beep(self.sound_active());
+                self.advance(address, 2)?;
+            }
+            Operation::AddToIndex { x } => {
+                self.index = self.index.wrapping_add(self.register[x as usize] as u16);
+                self.advance(address, 2)?;
+            }
+            Operation::SetIndexToSprite { x } => {
+                let character = (self.register[x as usize] & 0xF) as u16;
 
-                for i in 0..(n as u16) {
-                    let d = self.memory[(i + self.index) as usize];
-                    data.push(d);
+                self.index = self.config.font_address + character * FONT_CHARACTER_SIZE;
+                self.advance(address, 2)?;
+            }
+            Operation::StoreBinaryCodedDecimal { x } => {
+                let value = self.register[x as usize];
+                let digits = self.write_range(address, self.index as usize, 3)?;
+
+                self.memory[digits].copy_from_slice(&[value / 100, (value / 10) % 10, value % 10]);
+                self.advance(address, 2)?;
+            }
+            Operation::StoreRegistersInMemory { x } => {
+                let count = x as usize + 1;
+                let destination = self.write_range(address, self.index as usize, count)?;
+
+                self.memory[destination].copy_from_slice(&self.register[..count]);
+                if self.config.quirks.memory_increment {
+                    self.index = self.index.wrapping_add(x as u16 + 1);
                 }
+                self.advance(address, 2)?;
+            }
+            Operation::SetRegistersFromMemory { x } => {
+                let count = x as usize + 1;
+                let source = self.read_range(address, self.index as usize, count)?;
 
-                self.io.set_pixel(x.into(), y.into());
-                self.program_counter += 2;
+                self.register[..count].copy_from_slice(&self.memory[source]);
+                if self.config.quirks.memory_increment {
+                    self.index = self.index.wrapping_add(x as u16 + 1);
+                }
+                self.advance(address, 2)?;
+            }
+            Operation::ScrollDown { n } => {
+                self.framebuffer.scroll(0, n as isize, self.planes);
+                self.advance(address, 2)?;
                 self.draw_flag = true;
             }
-            Operation::SetDelayTimer { x } => {
-                println!("Setting timer: {}", x);
-                self.program_counter += 2;
+            Operation::ScrollRight => {
+                self.framebuffer.scroll(4, 0, self.planes);
+                self.advance(address, 2)?;
+                self.draw_flag = true;
             }
-            Operation::SetRegistersFromMemory { x } => {
-                for i in 0..x {
-                    self.register[i as usize] = self.memory[(self.index + i as u16) as usize];
+            Operation::ScrollLeft => {
+                self.framebuffer.scroll(-4, 0, self.planes);
+                self.advance(address, 2)?;
+                self.draw_flag = true;
+            }
+            Operation::Exit => return Ok(StepOutcome::Exited),
+            Operation::LowResolution => {
+                self.framebuffer = Framebuffer::default();
+                self.advance(address, 2)?;
+                self.draw_flag = true;
+            }
+            Operation::HighResolution => {
+                self.framebuffer = Framebuffer::new(HIRES_DISPLAY_WIDTH, HIRES_DISPLAY_HEIGHT);
+                self.advance(address, 2)?;
+                self.draw_flag = true;
+            }
+            Operation::SetIndexToLargeSprite { x } => {
+                let character = (self.register[x as usize] & 0xF) as u16;
+
+                self.index = self.config.big_font_address + character * BIG_FONT_CHARACTER_SIZE;
+                self.advance(address, 2)?;
+            }
+            Operation::StoreRegistersInFlags { x } => {
+                // Only as many registers as there are flags can be saved.
+                let count = (x as usize + 1).min(self.config.variant.flag_count());
+
+                self.flags[..count].copy_from_slice(&self.register[..count]);
+                self.advance(address, 2)?;
+            }
+            Operation::SetRegistersFromFlags { x } => {
+                let count = (x as usize + 1).min(self.config.variant.flag_count());
+
+                self.register[..count].copy_from_slice(&self.flags[..count]);
+                self.advance(address, 2)?;
+            }
+            Operation::ScrollUp { n } => {
+                self.framebuffer.scroll(0, -(n as isize), self.planes);
+                self.advance(address, 2)?;
+                self.draw_flag = true;
+            }
+            Operation::StoreRegisterRange { x, y } => {
+                let registers = register_range(x, y);
+                let destination =
+                    self.write_range(address, self.index as usize, registers.len())?;
+
+                for (address, register) in destination.zip(registers) {
+                    self.memory[address] = self.register[register];
                 }
-                self.program_counter += 2;
+                self.advance(address, 2)?;
+            }
+            Operation::LoadRegisterRange { x, y } => {
+                let registers = register_range(x, y);
+                let source = self.read_range(address, self.index as usize, registers.len())?;
+
+                for (address, register) in source.zip(registers) {
+                    self.register[register] = self.memory[address];
+                }
+                self.advance(address, 2)?;
+            }
+            Operation::SetIndexToLongAddress => {
+                let long = self.memory_range(address, address as usize + 2, 2)?;
+
+                self.index =
+                    u16::from_be_bytes([self.memory[long.start], self.memory[long.start + 1]]);
+                self.advance(address, 4)?;
+            }
+            Operation::SelectPlanes { x } => {
+                self.planes = x & 0x3;
+                self.advance(address, 2)?;
+            }
+            Operation::StoreAudioPattern => {
+                let source = self.read_range(address, self.index as usize, AUDIO_PATTERN_SIZE)?;
+
+                self.audio_pattern.copy_from_slice(&self.memory[source]);
+                self.io.set_pattern(Some(&self.audio_pattern), self.pitch);
+                self.advance(address, 2)?;
             }
-            _ => {
-                thread::sleep(Duration::from_secs(1));
-                panic!("Unhandled: {:#0x}", opcode);
+            Operation::SetPitch { x } => {
+                self.pitch = self.register[x as usize];
+                self.io.set_pattern(Some(&self.audio_pattern), self.pitch);
+                self.advance(address, 2)?;
             }
         };
+
+        self.cycles += cost;
+        // Single-stepping onto a breakpoint counts as stopping there.
+        self.paused_at = Some(self.program_counter);
+        Ok(StepOutcome::Executed)
+    }
+
+    /// Executes instructions until `budget` cycles have been spent, as set by the timing.
+    ///
+    /// Cycles spent past the budget are taken off the next one. Waiting on a key or the
+    /// display uses up the rest of the budget, as the machine would sit idle, and so does
+    /// reaching a breakpoint or watchpoint. Running again from a breakpoint executes the instruction there.
+    pub fn run_cycles(&mut self, budget: u64) -> Result<StepOutcome, ExecutionError> {
+        self.cycle_budget += budget as i64;
+
+        while self.cycle_budget > 0 {
+            let address = self.program_counter;
+            if self.breakpoints.contains(&address) && self.paused_at != Some(address) {
+                self.paused_at = Some(address);
+                self.cycle_budget = 0;
+                return Ok(StepOutcome::Breakpoint);
+            }
+
+            let before = self.cycles;
+            match self.step()? {
+                StepOutcome::Executed => {
+                    self.paused_at = None;
+                    self.cycle_budget -= (self.cycles - before) as i64;
+                    if !self.watch_hits.is_empty() {
+                        self.cycle_budget = 0;
+                        return Ok(StepOutcome::Watchpoint);
+                    }
+                }
+                StepOutcome::Waiting => {
+                    self.paused_at = Some(address);
+                    self.cycle_budget = 0;
+                    return Ok(StepOutcome::Waiting);
+                }
+                outcome => return Ok(outcome),
+            }
+        }
+
+        Ok(StepOutcome::Executed)
+    }
+
+    /// Runs one 60 Hz frame: `budget` cycles of instructions, then a timer tick, then the
+    /// framebuffer is presented if it changed.
+    ///
+    /// With the default timing every instruction is one cycle, so the budget is the
+    /// number of instructions per frame. Nothing is ticked or presented after an exit, or
+    /// when a breakpoint or watchpoint cuts the frame short.
+    pub fn run_frame(&mut self, budget: u64) -> Result<StepOutcome, ExecutionError> {
+        let outcome = self.run_cycles(budget)?;
+        if matches!(
+            outcome,
+            StepOutcome::Exited | StepOutcome::Breakpoint | StepOutcome::Watchpoint
+        ) {
+            return Ok(outcome);
+        }
+
+        self.tick_timers();
+        if self.draw_flag {
+            self.draw();
+            self.draw_flag = false;
+        }
+        Ok(outcome)
+    }
+
+    /// Cycles spent executing instructions since the last reset.
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    /// Moves the program counter past the instruction following the current one.
+    ///
+    /// On XO-CHIP this has to account for F000 NNNN being twice as long as other instructions.
+    fn skip_next_instruction(&mut self, address: u16) -> Result<(), ExecutionError> {
+        let next = self.program_counter as usize + 2;
+        let long = self.config.variant.has_xo_chip()
+            && self.memory.get(next..next + 2) == Some(&[0xF0, 0x00]);
+
+        self.advance(address, if long { 4 } else { 2 })?;
+        if self.config.timing == Timing::CosmacVip {
+            self.cycles += VIP_SKIP_CYCLES;
+        }
+        Ok(())
+    }
+
+    /// Moves the program counter `by` bytes on, for the instruction at `address`.
+    ///
+    /// The end of XO-CHIP's 64 KiB of memory is the end of the address space, so the
+    /// program counter can not go past it.
+    fn advance(&mut self, address: u16, by: u16) -> Result<(), ExecutionError> {
+        self.program_counter = self
+            .program_counter
+            .checked_add(by)
+            .ok_or(ExecutionError::ProgramCounterOutOfBounds { address })?;
+        Ok(())
+    }
+
+    /// Checks `len` bytes starting at `start` lie in memory, for the instruction at `address`.
+    fn memory_range(
+        &self,
+        address: u16,
+        start: usize,
+        len: usize,
+    ) -> Result<Range<usize>, ExecutionError> {
+        let end = start + len;
+        if end > self.memory.len() {
+            return Err(ExecutionError::MemoryOutOfBounds {
+                address,
+                target: end - 1,
+            });
+        }
+        Ok(start..end)
+    }
+
+    /// Checks memory an instruction reads from lies in memory, noting the access if it
+    /// may be watched.
+    fn read_range(
+        &mut self,
+        address: u16,
+        start: usize,
+        len: usize,
+    ) -> Result<Range<usize>, ExecutionError> {
+        let range = self.memory_range(address, start, len)?;
+        if !self.watchpoints.is_empty() {
+            self.accesses
+                .push((range.clone(), Access::Read, Vec::new()));
+        }
+        Ok(range)
+    }
+
+    /// Checks memory an instruction writes to lies in memory, noting the access and the
+    /// bytes about to be overwritten if it may be watched.
+    fn write_range(
+        &mut self,
+        address: u16,
+        start: usize,
+        len: usize,
+    ) -> Result<Range<usize>, ExecutionError> {
+        let range = self.memory_range(address, start, len)?;
+        if !self.watchpoints.is_empty() {
+            let old = self.memory[range.clone()].to_vec();
+            self.accesses.push((range.clone(), Access::Write, old));
+        }
+        Ok(range)
+    }
+
+    /// Turns the accesses of the instruction at `address` into watch hits, comparing the
+    /// registers to what they held before it ran.
+    fn check_watchpoints(&mut self, address: u16, register: &[u8; 16], index: u16) {
+        for (range, access, old) in std::mem::take(&mut self.accesses) {
+            for (i, target) in range.enumerate() {
+                let target = target as u16;
+                if !self.watchpoints.iter().any(|w| w.watches(target, access)) {
+                    continue;
+                }
+
+                let new = self.memory[target as usize] as u16;
+                self.watch_hits.push(WatchHit {
+                    pc: address,
+                    target: WatchTarget::Memory(target),
+                    access,
+                    old: old.get(i).map_or(new, |old| *old as u16),
+                    new,
+                });
+            }
+        }
+
+        for watchpoint in &self.watchpoints {
+            let (register, old, new) = match *watchpoint {
+                Watchpoint::Register(Register::V(x)) => (
+                    Register::V(x),
+                    register[x as usize] as u16,
+                    self.register[x as usize] as u16,
+                ),
+                Watchpoint::Register(Register::I) => (Register::I, index, self.index),
+                Watchpoint::Memory { .. } => continue,
+            };
+            if old != new {
+                self.watch_hits.push(WatchHit {
+                    pc: address,
+                    target: WatchTarget::Register(register),
+                    access: Access::Write,
+                    old,
+                    new,
+                });
+            }
+        }
+    }
+
+    fn reset_flag_after_logic(&mut self) {
+        if self.config.quirks.vf_reset {
+            self.register[0xF] = 0;
+        }
+    }
+
+    /// The value 8XY6 and 8XYE shift into VX.
+    fn shift_source(&self, x: u8, y: u8) -> u8 {
+        if self.config.quirks.shift_in_place {
+            self.register[x as usize]
+        } else {
+            self.register[y as usize]
+        }
     }
 
     pub fn draw(&mut self) {
-        self.io.clear();
-        self.io.draw();
-        self.io.present();
+        self.io.present(&self.framebuffer);
+    }
+}
+
+fn random_source(seed: Option<u64>) -> Box<dyn RandomSource> {
+    Box::new(seed.map_or_else(XorShift::from_time, XorShift::new))
+}
+
+/// Registers from VX to VY inclusive, counting down when X is greater than Y.
+fn register_range(x: u8, y: u8) -> Vec<usize> {
+    if x <= y {
+        (x as usize..=y as usize).collect()
+    } else {
+        (y as usize..=x as usize).rev().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::*;
+
+    use super::*;
+    use crate::config::Variant;
+
+    /// Loads `program` and executes `steps` instructions of it.
+    fn run(program: &[u8], steps: usize) -> System {
+        let mut system = System::default();
+        system.load_rom(program).unwrap();
+        for _ in 0..steps {
+            system.step().unwrap();
+        }
+        system
+    }
+
+    #[rstest]
+    #[case(0x8014, 0x10, 0x20, 0x30, 0)]
+    #[case(0x8014, 0xFF, 0x02, 0x01, 1)]
+    #[case(0x8015, 0x20, 0x10, 0x10, 1)]
+    #[case(0x8015, 0x10, 0x10, 0x00, 1)]
+    #[case(0x8015, 0x10, 0x20, 0xF0, 0)]
+    #[case(0x8017, 0x10, 0x20, 0x10, 1)]
+    #[case(0x8017, 0x20, 0x10, 0xF0, 0)]
+    #[case(0x8016, 0x05, 0x00, 0x02, 1)]
+    #[case(0x801E, 0x81, 0x00, 0x02, 1)]
+    fn test_arithmetic(
+        #[case] opcode: u16,
+        #[case] v0: u8,
+        #[case] v1: u8,
+        #[case] expected: u8,
+        #[case] expected_flag: u8,
+    ) {
+        let [hi, lo] = opcode.to_be_bytes();
+        let system = run(&[0x60, v0, 0x61, v1, hi, lo], 3);
+
+        assert_eq!(system.register[0], expected);
+        assert_eq!(system.register[0xF], expected_flag);
+        assert_eq!(system.program_counter, 0x206);
+    }
+
+    #[test]
+    fn test_subroutine_call_and_return() {
+        let system = run(
+            &[
+                0x22, 0x06, // CALL 0x206
+                0x60, 0x01, // LD V0, 1
+                0x12, 0x04, // JP 0x204
+                0x61, 0x02, // LD V1, 2
+                0x00, 0xEE, // RET
+            ],
+            4,
+        );
+
+        assert_eq!(system.register[..2], [1, 2]);
+        assert_eq!(system.stack_pointer, 0);
+    }
+
+    #[test]
+    fn test_binary_coded_decimal_and_register_dump() {
+        let system = run(
+            &[
+                0x60, 0xFE, // LD V0, 254
+                0xA3, 0x00, // LD I, 0x300
+                0xF0, 0x33, // LD B, V0
+                0xF2, 0x65, // LD V2, [I]
+                0xA3, 0x10, // LD I, 0x310
+                0xF2, 0x55, // LD [I], V2
+            ],
+            6,
+        );
+
+        assert_eq!(system.memory[0x300..0x303], [2, 5, 4]);
+        assert_eq!(system.register[..3], [2, 5, 4]);
+        assert_eq!(system.memory[0x310..0x313], [2, 5, 4]);
+        assert_eq!(system.index, 0x310);
+    }
+
+    #[test]
+    fn test_index_operations() {
+        let system = run(&[0x60, 0x0A, 0xA1, 0x00, 0xF0, 0x1E], 3);
+        assert_eq!(system.index, 0x10A);
+
+        let system = run(&[0x60, 0x0A, 0xF0, 0x29], 2);
+        assert_eq!(system.index, 0x50 + 0xA * FONT_CHARACTER_SIZE);
+    }
+
+    #[rstest]
+    #[case(0x000)]
+    #[case(0x050)]
+    #[case(0x1B0)]
+    fn test_font_is_loaded_at_configured_address(#[case] font_address: u16) {
+        let mut system = System::with_config(
+            Headless::default(),
+            Config {
+                font_address,
+                ..Config::default()
+            },
+        )
+        .unwrap();
+        system.load_rom(&[0x60, 0x07, 0xF0, 0x29]).unwrap();
+        system.step().unwrap();
+        system.step().unwrap();
+
+        let glyph = system.index as usize;
+        assert_eq!(system.index, font_address + 7 * FONT_CHARACTER_SIZE);
+        assert_eq!(system.memory[glyph..glyph + 5], FONT_SET[35..40]);
+
+        system.memory[0x300] = 0xAA;
+        system.reset();
+        assert_eq!(system.memory[0x300], 0);
+        assert_eq!(system.program_counter, 0x200);
+        assert_eq!(system.memory[glyph..glyph + 5], FONT_SET[35..40]);
+    }
+
+    #[rstest]
+    #[case(Variant::Chip8, 0xFC0, 0xA0, ConfigError::FontOutOfBounds { address: 0xFC0, size: 80 })]
+    #[case(Variant::Chip8, 0x50, 0xFFF, ConfigError::FontOutOfBounds { address: 0xFFF, size: 160 })]
+    #[case(Variant::XoChip, 0xFFFF, 0xA0, ConfigError::FontOutOfBounds { address: 0xFFFF, size: 80 })]
+    #[case(Variant::Chip8, 0xA0, 0xA0, ConfigError::FontsOverlap { font_address: 0xA0, big_font_address: 0xA0 })]
+    #[case(Variant::Chip8, 0x130, 0xA0, ConfigError::FontsOverlap { font_address: 0x130, big_font_address: 0xA0 })]
+    #[case(Variant::Chip8, 0x00, 0x40, ConfigError::FontsOverlap { font_address: 0x00, big_font_address: 0x40 })]
+    #[case(Variant::Chip8, 0x200, 0xA0, ConfigError::FontOverlapsProgram { address: 0x200, load_address: 0x200 })]
+    #[case(Variant::Chip8, 0x1C0, 0xA0, ConfigError::FontOverlapsProgram { address: 0x1C0, load_address: 0x200 })]
+    #[case(Variant::XoChip, 0x50, 0x180, ConfigError::FontOverlapsProgram { address: 0x180, load_address: 0x200 })]
+    fn test_fonts_must_fit(
+        #[case] variant: Variant,
+        #[case] font_address: u16,
+        #[case] big_font_address: u16,
+        #[case] expected: ConfigError,
+    ) {
+        let config = Config {
+            variant,
+            font_address,
+            big_font_address,
+            ..Config::default()
+        };
+
+        assert_eq!(
+            System::with_config(Headless::default(), config).err(),
+            Some(expected)
+        );
+    }
+
+    #[test]
+    fn test_jump_with_offset() {
+        let system = run(&[0x60, 0x04, 0xB3, 0x00], 2);
+        assert_eq!(system.program_counter, 0x304);
+    }
+
+    #[test]
+    fn test_random_number_is_masked() {
+        let system = run(&[0xC0, 0x0F, 0xC1, 0x00], 2);
+        assert!(system.register[0] <= 0x0F);
+        assert_eq!(system.register[1], 0);
+    }
+
+    #[test]
+    fn test_random_source_is_pluggable() {
+        struct Constant(u8);
+
+        impl RandomSource for Constant {
+            fn next_byte(&mut self) -> u8 {
+                self.0
+            }
+        }
+
+        let mut system = System::default();
+        system.set_random_source(Box::new(Constant(0xA5)));
+        system.load_rom(&[0xC0, 0xFF, 0xC1, 0x0F]).unwrap();
+        system.step().unwrap();
+        system.step().unwrap();
+
+        assert_eq!(system.register[..2], [0xA5, 0x05]);
+    }
+
+    #[rstest]
+    #[case(Timing::Instructions, 3, 3)]
+    #[case(Timing::CosmacVip, 100, 148)]
+    #[case(Timing::CosmacVip, 200, 250)]
+    fn test_run_cycles(#[case] timing: Timing, #[case] budget: u64, #[case] expected: u64) {
+        let config = Config {
+            timing,
+            ..Config::default()
+        };
+        let mut system = System::with_config(Headless::default(), config).unwrap();
+        system
+            .load_rom(&[0x60, 0x01, 0x70, 0x01, 0x12, 0x02])
+            .unwrap();
+
+        assert_eq!(system.run_cycles(budget), Ok(StepOutcome::Executed));
+        assert_eq!(system.cycles(), expected);
+    }
+
+    #[test]
+    fn test_run_cycles_carries_over_excess() {
+        let config = Config {
+            timing: Timing::CosmacVip,
+            ..Config::default()
+        };
+        let mut system = System::with_config(Headless::default(), config).unwrap();
+        system.load_rom(&[0x00, 0xE0, 0x12, 0x00]).unwrap();
+
+        system.run_cycles(10).unwrap();
+        assert_eq!(system.cycles(), 3118);
+        system.run_cycles(3000).unwrap();
+        assert_eq!(system.cycles(), 3118);
+        system.run_cycles(110).unwrap();
+        assert_eq!(system.cycles(), 3118 + 52);
+    }
+
+    #[test]
+    fn test_run_cycles_stops_while_waiting() {
+        let system = &mut run_with_quirks(&[0xF0, 0x0A], 0, Quirks::default());
+
+        assert_eq!(system.run_cycles(1000), Ok(StepOutcome::Waiting));
+        assert_eq!(system.cycles(), 0);
+    }
+
+    #[rstest]
+    #[case(0xE09E, true, 0x206)]
+    #[case(0xE09E, false, 0x204)]
+    #[case(0xE0A1, true, 0x204)]
+    #[case(0xE0A1, false, 0x206)]
+    fn test_key_skips(#[case] opcode: u16, #[case] pressed: bool, #[case] expected_pc: u16) {
+        let [hi, lo] = opcode.to_be_bytes();
+        let mut system = run(&[0x60, 0x07, hi, lo], 1);
+        system.keypad_mut().set(7, pressed);
+        system.step().unwrap();
+
+        assert_eq!(system.program_counter, expected_pc);
+    }
+
+    #[test]
+    fn test_wait_for_keypress_stores_key_on_release() {
+        let mut system = run(&[0xF3, 0x0A], 1);
+        assert_eq!(system.program_counter, 0x200);
+
+        system.keypad_mut().press(0xB);
+        system.step().unwrap();
+        system.step().unwrap();
+        assert_eq!(system.program_counter, 0x200);
+
+        system.keypad_mut().release(0xB);
+        system.step().unwrap();
+        assert_eq!(system.program_counter, 0x202);
+        assert_eq!(system.register[3], 0xB);
+    }
+
+    #[test]
+    fn test_sprites_are_clipped_at_the_edge() {
+        let mut system = System::default();
+        system
+            .load_rom(&[
+                0x60, 0x3C, 0x61, 0x1E, 0xA2, 0x0A, 0xD0, 0x14, 0xFF, 0xFF, 0xFF, 0xFF,
+            ])
+            .unwrap();
+        for _ in 0..4 {
+            system.step().unwrap();
+        }
+
+        let lit = system
+            .framebuffer()
+            .pixels()
+            .iter()
+            .filter(|p| **p > 0)
+            .count();
+        assert_eq!(lit, 4 * 2);
+        assert_eq!(system.framebuffer().get(63, 31), 1);
+        assert_eq!(system.framebuffer().get(0, 0), 0);
+    }
+
+    /// Loads `program` with `quirks` and executes `steps` instructions of it.
+    fn run_with_quirks(program: &[u8], steps: usize, quirks: Quirks) -> System {
+        let config = Config {
+            quirks,
+            ..Config::default()
+        };
+        let mut system = System::with_config(Headless::default(), config).unwrap();
+        system.load_rom(program).unwrap();
+        for _ in 0..steps {
+            system.step().unwrap();
+        }
+        system
+    }
+
+    #[rstest]
+    #[case(Quirks::vip(), 0)]
+    #[case(Quirks::schip(), 1)]
+    fn test_vf_reset_quirk(#[case] quirks: Quirks, #[case] expected_flag: u8) {
+        let system = run_with_quirks(&[0x6F, 0x01, 0x81, 0x21], 2, quirks);
+        assert_eq!(system.register[0xF], expected_flag);
+    }
+
+    #[rstest]
+    #[case(Quirks::vip(), 0x303)]
+    #[case(Quirks::schip(), 0x300)]
+    fn test_memory_increment_quirk(#[case] quirks: Quirks, #[case] expected_index: u16) {
+        let system = run_with_quirks(&[0xA3, 0x00, 0xF2, 0x55], 2, quirks);
+        assert_eq!(system.index, expected_index);
+
+        let system = run_with_quirks(&[0xA3, 0x00, 0xF2, 0x65], 2, quirks);
+        assert_eq!(system.index, expected_index);
+    }
+
+    #[rstest]
+    #[case(Quirks::vip(), 0x08, 0)]
+    #[case(Quirks::schip(), 0x01, 1)]
+    fn test_shift_quirk(#[case] quirks: Quirks, #[case] expected: u8, #[case] flag: u8) {
+        let system = run_with_quirks(&[0x60, 0x03, 0x61, 0x10, 0x80, 0x16], 3, quirks);
+        assert_eq!(system.register[0], expected);
+        assert_eq!(system.register[0xF], flag);
+    }
+
+    #[rstest]
+    #[case(Quirks::vip(), 0x301)]
+    #[case(Quirks::schip(), 0x303)]
+    fn test_jump_quirk(#[case] quirks: Quirks, #[case] expected_pc: u16) {
+        let system = run_with_quirks(&[0x60, 0x01, 0x63, 0x03, 0xB3, 0x00], 3, quirks);
+        assert_eq!(system.program_counter, expected_pc);
+    }
+
+    #[rstest]
+    #[case(Quirks::vip(), 0)]
+    #[case(Quirks::xochip(), 1)]
+    fn test_sprite_wrap_quirk(#[case] quirks: Quirks, #[case] expected: u8) {
+        let system = run_with_quirks(
+            &[
+                0x60, 0x3C, 0x61, 0x1E, 0xA2, 0x0C, 0x6F, 0x00, 0xD0, 0x14, 0x00, 0x00, 0xFF, 0xFF,
+                0xFF, 0xFF,
+            ],
+            5,
+            Quirks {
+                display_wait: false,
+                ..quirks
+            },
+        );
+        assert_eq!(system.framebuffer().get(0, 0), expected);
+        assert_eq!(system.framebuffer().get(63, 31), 1);
+    }
+
+    #[test]
+    fn test_display_wait_quirk() {
+        let mut system = run_with_quirks(&[0xD0, 0x01, 0xD0, 0x01], 3, Quirks::vip());
+        assert_eq!(system.program_counter, 0x200);
+
+        system.tick_timers();
+        system.step().unwrap();
+        system.step().unwrap();
+        assert_eq!(system.program_counter, 0x202);
+
+        system.tick_timers();
+        system.step().unwrap();
+        assert_eq!(system.program_counter, 0x204);
+    }
+
+    /// Loads `program` on a SUPER-CHIP and executes `steps` instructions of it.
+    fn run_super_chip(program: &[u8], steps: usize) -> System {
+        let config = Config {
+            variant: Variant::SuperChip,
+            ..Config::default()
+        };
+        let mut system = System::with_config(Headless::default(), config).unwrap();
+        system.load_rom(program).unwrap();
+        for _ in 0..steps {
+            system.step().unwrap();
+        }
+        system
+    }
+
+    #[rstest]
+    #[case(0x00FF)]
+    #[case(0x00C1)]
+    #[case(0xF075)]
+    fn test_super_chip_instructions_need_super_chip(#[case] opcode: u16) {
+        let mut system = System::default();
+        system.load_rom(&opcode.to_be_bytes()).unwrap();
+
+        assert_eq!(
+            system.step(),
+            Err(ExecutionError::InvalidOpcode {
+                address: 0x200,
+                word: opcode
+            })
+        );
+    }
+
+    #[test]
+    fn test_resolution_switch() {
+        let system = run_super_chip(&[0x00, 0xFF], 1);
+        assert_eq!(system.framebuffer().width(), 128);
+        assert_eq!(system.framebuffer().height(), 64);
+
+        let system = run_super_chip(&[0x00, 0xFF, 0x00, 0xFE], 2);
+        assert_eq!(system.framebuffer().width(), 64);
+        assert_eq!(system.framebuffer().height(), 32);
+    }
+
+    #[test]
+    fn test_draws_large_sprite() {
+        let mut program = vec![
+            0x00, 0xFF, // HIGH
+            0x60, 0x70, // LD V0, 112
+            0xA2, 0x0A, // LD I, 0x20A
+            0xD0, 0x00, // DRW V0, V0, 0
+            0x00, 0x00,
+        ];
+        program.extend([0xFF; 32]);
+        let system = run_super_chip(&program, 4);
+
+        let lit = system
+            .framebuffer()
+            .pixels()
+            .iter()
+            .filter(|p| **p > 0)
+            .count();
+        assert_eq!(lit, 16 * 16);
+        assert_eq!(system.framebuffer().get(112, 112 % 64), 1);
+        assert_eq!(system.framebuffer().get(127, 63), 1);
+    }
+
+    #[test]
+    fn test_scrolling() {
+        let program = [
+            0x00, 0xFF, // HIGH
+            0xA2, 0x0C, // LD I, 0x20C
+            0xD0, 0x01, // DRW V0, V0, 1
+            0x00, 0xC3, // SCD 3
+            0x00, 0xFB, // SCR
+            0x00, 0xFC, // SCL
+            0x80, 0x00,
+        ];
+
+        let system = run_super_chip(&program, 4);
+        assert_eq!(system.framebuffer().get(0, 3), 1);
+
+        let system = run_super_chip(&program, 5);
+        assert_eq!(system.framebuffer().get(4, 3), 1);
+        assert_eq!(system.framebuffer().get(0, 3), 0);
+
+        let system = run_super_chip(&program, 6);
+        assert_eq!(system.framebuffer().get(0, 3), 1);
+    }
+
+    #[test]
+    fn test_large_font() {
+        let system = run_super_chip(&[0x60, 0x09, 0xF0, 0x30], 2);
+        let glyph = system.index as usize;
+
+        assert_eq!(system.index, 0xA0 + 9 * BIG_FONT_CHARACTER_SIZE);
+        assert_eq!(system.memory[glyph..glyph + 10], BIG_FONT_SET[90..100]);
+    }
+
+    #[test]
+    fn test_flags_survive_reset() {
+        let mut system = run_super_chip(&[0x60, 0x01, 0x61, 0x02, 0xF1, 0x75], 3);
+        system.reset();
+        system.load_rom(&[0xF1, 0x85]).unwrap();
+        system.step().unwrap();
+
+        assert_eq!(system.register[..2], [1, 2]);
+    }
+
+    #[test]
+    fn test_exit() {
+        let mut system = run_super_chip(&[0x00, 0xFD], 0);
+
+        assert_eq!(system.step(), Ok(StepOutcome::Exited));
+        assert_eq!(system.program_counter, 0x200);
+    }
+
+    /// Loads `program` on an XO-CHIP and executes `steps` instructions of it.
+    fn run_xo_chip(program: &[u8], steps: usize) -> System {
+        let config = Config {
+            variant: Variant::XoChip,
+            quirks: Quirks::xochip(),
+            ..Config::default()
+        };
+        let mut system = System::with_config(Headless::default(), config).unwrap();
+        system.load_rom(program).unwrap();
+        for _ in 0..steps {
+            system.step().unwrap();
+        }
+        system
+    }
+
+    #[rstest]
+    #[case(0x5122)]
+    #[case(0xF000)]
+    #[case(0xF201)]
+    #[case(0xF03A)]
+    fn test_xo_chip_instructions_need_xo_chip(#[case] opcode: u16) {
+        let config = Config {
+            variant: Variant::SuperChip,
+            ..Config::default()
+        };
+        let mut system = System::with_config(Headless::default(), config).unwrap();
+        system.load_rom(&opcode.to_be_bytes()).unwrap();
+
+        assert!(matches!(
+            system.step(),
+            Err(ExecutionError::InvalidOpcode { .. })
+        ));
+    }
+
+    #[test]
+    fn test_xo_chip_has_64k_of_memory() {
+        let mut system = run_xo_chip(&[0x00, 0x00], 0);
+        assert_eq!(system.memory.len(), 65536);
+        assert!(system.load_rom(&vec![0; 65536 - 0x200]).is_ok());
+    }
+
+    #[test]
+    fn test_long_index_load() {
+        let system = run_xo_chip(&[0xF0, 0x00, 0xBE, 0xEF], 1);
+        assert_eq!(system.index, 0xBEEF);
+        assert_eq!(system.program_counter, 0x204);
+    }
+
+    #[rstest]
+    #[case(Variant::XoChip, 0x206)]
+    #[case(Variant::SuperChip, 0x204)]
+    fn test_skips_over_long_index_load(#[case] variant: Variant, #[case] expected_pc: u16) {
+        let config = Config {
+            variant,
+            ..Config::default()
+        };
+        let mut system = System::with_config(Headless::default(), config).unwrap();
+        system
+            .load_rom(&[0x30, 0x00, 0xF0, 0x00, 0x12, 0x34])
+            .unwrap();
+        system.step().unwrap();
+
+        assert_eq!(system.program_counter, expected_pc);
+    }
+
+    #[rstest]
+    #[case(0x6001)]
+    #[case(0x2300)]
+    #[case(0x3000)]
+    #[case(0x4001)]
+    fn test_program_counter_can_not_pass_end_of_memory(#[case] opcode: u16) {
+        let mut system = run_xo_chip(&[0x00, 0x00], 0);
+        system.memory[0xFFFE..].copy_from_slice(&opcode.to_be_bytes());
+        system.program_counter = 0xFFFE;
+
+        assert_eq!(
+            system.step(),
+            Err(ExecutionError::ProgramCounterOutOfBounds { address: 0xFFFE })
+        );
+    }
+
+    #[test]
+    fn test_register_ranges() {
+        let program = [
+            0x61, 0x01, // LD V1, 1
+            0x62, 0x02, // LD V2, 2
+            0x63, 0x03, // LD V3, 3
+            0xA3, 0x00, // LD I, 0x300
+            0x51, 0x32, // SAVE V1 - V3
+            0xA3, 0x10, // LD I, 0x310
+            0x53, 0x12, // SAVE V3 - V1
+            0x57, 0x53, // LOAD V7 - V5
+        ];
+        let system = run_xo_chip(&program, 8);
+
+        assert_eq!(system.memory[0x300..0x303], [1, 2, 3]);
+        assert_eq!(system.memory[0x310..0x313], [3, 2, 1]);
+        assert_eq!(system.register[5..8], [1, 2, 3]);
+        assert_eq!(system.index, 0x310);
+    }
+
+    #[test]
+    fn test_draws_to_selected_planes() {
+        let program = [
+            0xF3, 0x01, // PLANE 3
+            0xA2, 0x08, // LD I, 0x208
+            0xD0, 0x01, // DRW V0, V0, 1
+            0x12, 0x06, // JP 0x206
+            0xF0, 0x80, // plane 1 sprite, plane 2 sprite
+        ];
+        let mut system = run_xo_chip(&program, 3);
+
+        assert_eq!(system.framebuffer().get(0, 0), 3);
+        assert_eq!(system.framebuffer().get(1, 0), 1);
+        assert_eq!(system.framebuffer().get(4, 0), 0);
+
+        system.planes = 2;
+        system.memory[0x206..0x208].copy_from_slice(&[0x00, 0xE0]);
+        system.step().unwrap();
+        assert_eq!(system.framebuffer().get(0, 0), 1);
+    }
+
+    #[test]
+    fn test_audio_pattern_and_pitch() {
+        let mut program = vec![
+            0xA2, 0x08, // LD I, 0x208
+            0xF0, 0x02, // AUDIO
+            0x60, 0x70, // LD V0, 112
+            0xF0, 0x3A, // PITCH V0
+        ];
+        program.extend(0..16);
+        let system = run_xo_chip(&program, 4);
+
+        let pattern: [u8; 16] = core::array::from_fn(|i| i as u8);
+        assert_eq!(system.io().pattern, Some((pattern, 112)));
+    }
+
+    #[test]
+    fn test_loading_state_restores_sound() {
+        let mut program = vec![
+            0xA2, 0x06, // LD I, 0x206
+            0xF0, 0x02, // AUDIO
+            0x60, 0x70, // LD V0, 112
+        ];
+        program.extend([0xFF; 16]);
+        let mut system = run_xo_chip(&program, 0);
+        let before = system.save_state();
+        system.step().unwrap();
+        system.step().unwrap();
+        let after = system.save_state();
+
+        system.load_state(&before);
+        assert_eq!(system.io().pattern, None);
+        system.load_state(&after);
+        assert_eq!(system.io().pattern, Some(([0xFF; 16], DEFAULT_PITCH)));
     }
 }
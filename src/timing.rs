@@ -0,0 +1,118 @@
+use crate::opcode::Operation;
+
+/// Machine cycles of the VIP's 1802 processor in one 60 Hz frame, at 1.76 MHz.
+pub const VIP_CYCLES_PER_FRAME: u64 = 3668;
+
+/// Machine cycles each frame lost to the display DMA and the interrupt routine that
+/// counts down the timers, which are not available to the interpreter.
+pub const VIP_INTERRUPT_CYCLES: u64 = 1108;
+
+/// Extra machine cycles a skip instruction takes when the skip happens.
+pub const VIP_SKIP_CYCLES: u64 = 4;
+
+/// Machine cycles the interpreter spends fetching and decoding every instruction.
+const FETCH_CYCLES: u64 = 40;
+
+/// Machine cycles the COSMAC VIP interpreter takes to execute an operation, given the
+/// registers it runs with, not counting a taken skip.
+///
+/// The counts are approximations of the routines in the VIP interpreter ROM. Operations
+/// the VIP does not have are given the cost of the closest one it does.
+///
+/// Source: https://laurencescotford.com/chip-8-on-the-cosmac-vip-index/
+pub fn vip_cycles(operation: &Operation, register: &[u8; 16]) -> u64 {
+    let execute = match *operation {
+        Operation::NoOperation => 0,
+        Operation::ClearDisplay => 3078,
+        Operation::SubroutineReturn => 10,
+        Operation::GotoAddress { .. } => 12,
+        Operation::SubroutineCall { .. } => 26,
+        Operation::EqualityCheck { .. } | Operation::InequalityCheck { .. } => 10,
+        Operation::EqualityRegisterCheck { .. } | Operation::InequalityRegisterCheck { .. } => 14,
+        Operation::SetRegister { .. } => 6,
+        Operation::AddRegister { .. } => 10,
+        Operation::SetRegisterFromRegister { .. }
+        | Operation::BitwiseOr { .. }
+        | Operation::BitwiseAnd { .. }
+        | Operation::BitwiseXor { .. }
+        | Operation::AddValues { .. }
+        | Operation::SubtractValues { .. }
+        | Operation::StoreLeastSignificant { .. }
+        | Operation::SubtractValueFromRegister { .. }
+        | Operation::StoreMostSignificant { .. } => 44,
+        Operation::SetIndexToAddress { .. } => 12,
+        Operation::GotoAddressWithRegister { .. } => 22,
+        Operation::AssignRandomNumber { .. } => 36,
+        Operation::DrawSprite { x, n, .. } => {
+            // Sprites not aligned to a byte are shifted into place one bit at a time.
+            let shift = register[x as usize] as u64 % 8;
+            let row = if shift == 0 { 34 } else { 48 + 8 * shift };
+            26 + n as u64 * row
+        }
+        Operation::SkipIfKeyPressed { .. } | Operation::SkipIfKeyNotPressed { .. } => 14,
+        Operation::GetDelayTimer { .. }
+        | Operation::StoreNextKeypress { .. }
+        | Operation::SetDelayTimer { .. }
+        | Operation::SetSoundTimer { .. } => 10,
+        Operation::AddToIndex { .. } => 16,
+        Operation::SetIndexToSprite { .. } => 16,
+        Operation::StoreBinaryCodedDecimal { x } => {
+            // Each digit is found by repeated subtraction.
+            let value = register[x as usize] as u64;
+            80 + 16 * (value / 100 + value / 10 % 10 + value % 10)
+        }
+        Operation::StoreRegistersInMemory { x } | Operation::SetRegistersFromMemory { x } => {
+            14 + 14 * (x as u64 + 1)
+        }
+        Operation::ScrollDown { .. }
+        | Operation::ScrollUp { .. }
+        | Operation::ScrollRight
+        | Operation::ScrollLeft
+        | Operation::LowResolution
+        | Operation::HighResolution => 3078,
+        Operation::Exit => 0,
+        Operation::SetIndexToLargeSprite { .. } => 16,
+        Operation::StoreRegistersInFlags { x } | Operation::SetRegistersFromFlags { x } => {
+            14 + 14 * (x as u64 + 1)
+        }
+        Operation::StoreRegisterRange { x, y } | Operation::LoadRegisterRange { x, y } => {
+            14 + 14 * (x.abs_diff(y) as u64 + 1)
+        }
+        Operation::SetIndexToLongAddress => 24,
+        Operation::SelectPlanes { .. } | Operation::SetPitch { .. } => 10,
+        Operation::StoreAudioPattern => 14 + 14 * 16,
+    };
+
+    FETCH_CYCLES + execute
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unaligned_sprites_are_slower() {
+        let mut register = [0; 16];
+        let aligned = vip_cycles(&Operation::DrawSprite { x: 0, y: 0, n: 5 }, &register);
+        register[0] = 3;
+        let unaligned = vip_cycles(&Operation::DrawSprite { x: 0, y: 0, n: 5 }, &register);
+
+        assert!(unaligned > aligned);
+        assert!(
+            vip_cycles(&Operation::DrawSprite { x: 0, y: 0, n: 15 }, &register)
+                > VIP_CYCLES_PER_FRAME / 4
+        );
+    }
+
+    #[test]
+    fn test_decimal_conversion_depends_on_digits() {
+        let mut register = [0; 16];
+        let zero = vip_cycles(&Operation::StoreBinaryCodedDecimal { x: 1 }, &register);
+        register[1] = 199;
+
+        assert_eq!(
+            vip_cycles(&Operation::StoreBinaryCodedDecimal { x: 1 }, &register),
+            zero + 16 * 19
+        );
+    }
+}
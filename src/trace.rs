@@ -0,0 +1,422 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{self, Write};
+use std::ops::RangeInclusive;
+
+use crate::error::{TraceError, TraceErrorKind};
+use crate::opcode::decode;
+
+/// The machine state an instruction was executed from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TraceEntry {
+    /// Number of instructions executed before this one.
+    pub ops: u64,
+    pub pc: u16,
+    pub opcode: u16,
+    pub registers: [u8; 16],
+    pub index: u16,
+    pub stack_pointer: u8,
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+}
+
+/// Writes the entry as one line of `key=value` fields in hexadecimal, apart from the
+/// decimal instruction count, followed by the instruction after a semicolon:
+///
+/// `ops=42 pc=0204 op=A300 v=2A000000000000000000000000000000 i=0300 sp=00 dt=00 st=00 ; LD I, 0x300`
+///
+/// Other tools can rely on this format not changing.
+impl fmt::Display for TraceEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "ops={} pc={:04X} op={:04X} v=",
+            self.ops, self.pc, self.opcode
+        )?;
+        for value in self.registers {
+            write!(f, "{:02X}", value)?;
+        }
+        write!(
+            f,
+            " i={:04X} sp={:02X} dt={:02X} st={:02X} ; ",
+            self.index, self.stack_pointer, self.delay_timer, self.sound_timer
+        )?;
+
+        match decode(self.opcode) {
+            Ok(operation) => write!(f, "{}", operation),
+            Err(_) => write!(f, "DW {:#06X}", self.opcode),
+        }
+    }
+}
+
+/// Receives an entry for every instruction the System executes, including one that fails.
+pub trait Tracer {
+    fn trace(&mut self, entry: &TraceEntry);
+
+    /// Called when tracing ends, to write out anything held back.
+    fn finish(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Writes trace entries to `W`, one per line.
+///
+/// With a limit, only the most recent entries are kept, and written out when the tracer
+/// finishes or is dropped. That way a crash still leaves the instructions leading up to it.
+pub struct TraceWriter<W: Write> {
+    out: W,
+    range: Option<RangeInclusive<u16>>,
+    limit: Option<usize>,
+    recent: VecDeque<TraceEntry>,
+    error: Option<io::Error>,
+}
+
+impl<W: Write> TraceWriter<W> {
+    pub fn new(out: W) -> Self {
+        TraceWriter {
+            out,
+            range: None,
+            limit: None,
+            recent: VecDeque::new(),
+            error: None,
+        }
+    }
+
+    /// Only traces instructions at addresses in `range`.
+    pub fn with_range(mut self, range: RangeInclusive<u16>) -> Self {
+        self.range = Some(range);
+        self
+    }
+
+    /// Only keeps the last `limit` entries.
+    pub fn with_limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    fn write_recent(&mut self) -> io::Result<()> {
+        for entry in self.recent.drain(..) {
+            writeln!(self.out, "{}", entry)?;
+        }
+        self.out.flush()
+    }
+}
+
+impl<W: Write> Tracer for TraceWriter<W> {
+    fn trace(&mut self, entry: &TraceEntry) {
+        if self.error.is_some() || self.range.as_ref().is_some_and(|r| !r.contains(&entry.pc)) {
+            return;
+        }
+
+        match self.limit {
+            Some(limit) => {
+                if self.recent.len() == limit {
+                    self.recent.pop_front();
+                }
+                if limit > 0 {
+                    self.recent.push_back(*entry);
+                }
+            }
+            None => {
+                if let Err(e) = writeln!(self.out, "{}", entry) {
+                    self.error = Some(e);
+                }
+            }
+        }
+    }
+
+    /// Writes out the last entries when limited, and reports the first error writing
+    /// any of them.
+    fn finish(&mut self) -> io::Result<()> {
+        if let Some(e) = self.error.take() {
+            return Err(e);
+        }
+        self.write_recent()
+    }
+}
+
+impl<W: Write> Drop for TraceWriter<W> {
+    fn drop(&mut self) {
+        let _ = self.write_recent();
+    }
+}
+
+/// An entry read back from a trace, which may have been written by another emulator.
+///
+/// Only the program counter is required. Registers can be given together, as `v=` and 32
+/// hexadecimal digits, or one at a time, as `v0=2A`. Unknown fields are ignored.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TraceRecord {
+    /// 1-based line the record was read from.
+    pub line: usize,
+    pub ops: Option<u64>,
+    pub pc: u16,
+    pub opcode: Option<u16>,
+    pub registers: [Option<u8>; 16],
+    pub index: Option<u16>,
+}
+
+impl TraceRecord {
+    /// The fields both records have, but with different values.
+    pub fn differences(&self, other: &TraceRecord) -> Vec<Difference> {
+        let mut differences = Vec::new();
+        if self.pc != other.pc {
+            differences.push(Difference::Pc(self.pc, other.pc));
+        }
+        for (x, (a, b)) in self.registers.iter().zip(&other.registers).enumerate() {
+            if let (Some(a), Some(b)) = (a, b) {
+                if a != b {
+                    differences.push(Difference::Register(x as u8, *a, *b));
+                }
+            }
+        }
+        if let (Some(a), Some(b)) = (self.index, other.index) {
+            if a != b {
+                differences.push(Difference::Index(a, b));
+            }
+        }
+        differences
+    }
+}
+
+/// A field with different values in two traces, given in the same order as the traces.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Difference {
+    Pc(u16, u16),
+    Register(u8, u8, u8),
+    Index(u16, u16),
+}
+
+impl fmt::Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Difference::Pc(a, b) => write!(f, "PC: {:#05X} != {:#05X}", a, b),
+            Difference::Register(x, a, b) => write!(f, "V{:X}: {:#04X} != {:#04X}", x, a, b),
+            Difference::Index(a, b) => write!(f, "I: {:#05X} != {:#05X}", a, b),
+        }
+    }
+}
+
+/// Reads every record in a trace, skipping blank lines and those starting with `#`.
+pub fn parse_trace(text: &str) -> Result<Vec<TraceRecord>, TraceError> {
+    text.lines()
+        .enumerate()
+        .filter_map(|(n, line)| {
+            // Everything after a semicolon is the instruction, for people to read.
+            let fields = line.split(';').next().unwrap_or_default().trim();
+            if fields.is_empty() || fields.starts_with('#') {
+                None
+            } else {
+                Some(parse_record(n + 1, fields))
+            }
+        })
+        .collect()
+}
+
+fn parse_record(line: usize, fields: &str) -> Result<TraceRecord, TraceError> {
+    let error = |kind| TraceError { line, kind };
+    let mut record = TraceRecord {
+        line,
+        ..TraceRecord::default()
+    };
+    let mut pc = None;
+
+    for field in fields.split_whitespace() {
+        let Some((key, value)) = field.split_once('=') else {
+            continue;
+        };
+        let invalid = || error(TraceErrorKind::InvalidField(field.to_string()));
+        let key = key.to_ascii_lowercase();
+
+        match key.as_str() {
+            "ops" => record.ops = Some(value.parse().map_err(|_| invalid())?),
+            "pc" => pc = Some(parse_hex(value).ok_or_else(invalid)?),
+            "op" => record.opcode = Some(parse_hex(value).ok_or_else(invalid)?),
+            "i" => record.index = Some(parse_hex(value).ok_or_else(invalid)?),
+            "v" => {
+                if value.len() != 32 {
+                    return Err(invalid());
+                }
+                for (x, register) in record.registers.iter_mut().enumerate() {
+                    *register = Some(
+                        value
+                            .get(2 * x..2 * x + 2)
+                            .and_then(parse_hex)
+                            .ok_or_else(invalid)?,
+                    );
+                }
+            }
+            _ => {
+                let x = key
+                    .strip_prefix('v')
+                    .filter(|x| x.len() == 1)
+                    .and_then(|x| u8::from_str_radix(x, 16).ok());
+                if let Some(x) = x {
+                    record.registers[x as usize] = Some(parse_hex(value).ok_or_else(invalid)?);
+                }
+            }
+        }
+    }
+
+    record.pc = pc.ok_or_else(|| error(TraceErrorKind::MissingPc))?;
+    Ok(record)
+}
+
+/// Parses hexadecimal, with or without a 0x prefix.
+fn parse_hex<T: TryFrom<u32>>(value: &str) -> Option<T> {
+    let digits = value
+        .strip_prefix("0x")
+        .or_else(|| value.strip_prefix("0X"))
+        .unwrap_or(value);
+    u32::from_str_radix(digits, 16)
+        .ok()
+        .and_then(|value| T::try_from(value).ok())
+}
+
+/// Position of the first records that differ when both traces are read in order, or of
+/// the first record only one of them has. None when the traces agree throughout.
+pub fn first_divergence(a: &[TraceRecord], b: &[TraceRecord]) -> Option<usize> {
+    a.iter()
+        .zip(b)
+        .position(|(a, b)| !a.differences(b).is_empty())
+        .or_else(|| (a.len() != b.len()).then(|| a.len().min(b.len())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(ops: u64, pc: u16) -> TraceEntry {
+        TraceEntry {
+            ops,
+            pc,
+            opcode: 0xA300,
+            registers: [0; 16],
+            index: 0x300,
+            stack_pointer: 1,
+            delay_timer: 0x3C,
+            sound_timer: 0,
+        }
+    }
+
+    /// Traces `entries` with a writer set up by `configure`, returning the first two
+    /// fields of every line written.
+    fn written(
+        configure: impl FnOnce(TraceWriter<&mut Vec<u8>>) -> TraceWriter<&mut Vec<u8>>,
+        entries: &[TraceEntry],
+    ) -> Vec<String> {
+        let mut out = Vec::new();
+        let mut tracer = configure(TraceWriter::new(&mut out));
+        for entry in entries {
+            tracer.trace(entry);
+        }
+        tracer.finish().unwrap();
+        drop(tracer);
+
+        String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .map(|line| line.split(' ').take(2).collect::<Vec<_>>().join(" "))
+            .collect()
+    }
+
+    #[test]
+    fn test_format() {
+        let mut entry = entry(42, 0x204);
+        entry.registers[0] = 0x2A;
+
+        assert_eq!(
+            entry.to_string(),
+            "ops=42 pc=0204 op=A300 v=2A000000000000000000000000000000 \
+             i=0300 sp=01 dt=3C st=00 ; LD I, 0x300"
+        );
+        entry.opcode = 0xFFFF;
+        assert!(entry.to_string().ends_with(" ; DW 0xFFFF"));
+    }
+
+    #[test]
+    fn test_range_and_limit() {
+        let entries: Vec<_> = (0..6)
+            .map(|ops| entry(ops, 0x200 + 2 * ops as u16))
+            .collect();
+
+        assert_eq!(
+            written(|tracer| tracer.with_range(0x202..=0x206), &entries),
+            ["ops=1 pc=0202", "ops=2 pc=0204", "ops=3 pc=0206"]
+        );
+        assert_eq!(
+            written(|tracer| tracer.with_limit(2), &entries),
+            ["ops=4 pc=0208", "ops=5 pc=020A"]
+        );
+    }
+
+    #[test]
+    fn test_limited_entries_are_written_when_dropped() {
+        let mut out = Vec::new();
+        {
+            let mut tracer = TraceWriter::new(&mut out).with_limit(1);
+            tracer.trace(&entry(0, 0x200));
+            tracer.trace(&entry(1, 0x202));
+        }
+        assert!(String::from_utf8(out)
+            .unwrap()
+            .starts_with("ops=1 pc=0202 "));
+    }
+
+    #[test]
+    fn test_parse_reads_back_entries() {
+        let mut written = entry(42, 0x204);
+        written.registers[0xF] = 0x01;
+        let text = format!("# header\n\n{}\n", written);
+
+        let records = parse_trace(&text).unwrap();
+        assert_eq!(records.len(), 1);
+        let record = &records[0];
+        assert_eq!(record.line, 3);
+        assert_eq!(record.ops, Some(42));
+        assert_eq!(
+            (record.pc, record.opcode, record.index),
+            (0x204, Some(0xA300), Some(0x300))
+        );
+        assert_eq!(record.registers[0], Some(0));
+        assert_eq!(record.registers[0xF], Some(1));
+    }
+
+    #[test]
+    fn test_parse_tolerates_missing_fields() {
+        let records = parse_trace("PC=0x200 V3=0x2a cycle=7\npc=202").unwrap();
+
+        assert_eq!(records[0].pc, 0x200);
+        assert_eq!(records[0].registers[3], Some(0x2A));
+        assert_eq!(records[0].registers[0], None);
+        assert_eq!((records[0].ops, records[0].index), (None, None));
+        assert_eq!(records[1].pc, 0x202);
+
+        assert_eq!(
+            parse_trace("pc=200\ni=300").unwrap_err(),
+            TraceError {
+                line: 2,
+                kind: TraceErrorKind::MissingPc
+            }
+        );
+        assert_eq!(
+            parse_trace("pc=200 v0=100").unwrap_err().kind,
+            TraceErrorKind::InvalidField("v0=100".to_string())
+        );
+    }
+
+    #[test]
+    fn test_first_divergence() {
+        let a = parse_trace("pc=200 v0=00\npc=202 v0=01 i=300\npc=204 v0=01 i=301").unwrap();
+        let mut b = parse_trace("pc=200\npc=202 v0=01\npc=204 v0=01 i=302").unwrap();
+
+        // I is only compared where both traces have it.
+        assert_eq!(first_divergence(&a, &b), Some(2));
+        assert_eq!(a[2].differences(&b[2]), [Difference::Index(0x301, 0x302)]);
+        assert_eq!(a[2].differences(&b[2])[0].to_string(), "I: 0x301 != 0x302");
+
+        b[2].index = None;
+        assert_eq!(first_divergence(&a, &b), None);
+        assert_eq!(first_divergence(&a, &b[..2]), Some(2));
+    }
+}
@@ -0,0 +1,126 @@
+use std::fmt;
+use std::ops::RangeInclusive;
+
+/// How memory is accessed, or which accesses a watchpoint fires on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+
+    /// Both reads and writes. Accesses themselves are never this.
+    ReadWrite,
+}
+
+impl Access {
+    fn includes(self, access: Access) -> bool {
+        self == Access::ReadWrite || self == access
+    }
+}
+
+/// A register a watchpoint can be set on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Register {
+    V(u8),
+    I,
+}
+
+impl fmt::Display for Register {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Register::V(x) => write!(f, "V{:X}", x),
+            Register::I => write!(f, "I"),
+        }
+    }
+}
+
+/// Something the System reports on when an instruction touches it.
+///
+/// Memory watchpoints fire on every access of the right kind, even when a write stores the
+/// value that was already there. Register watchpoints fire when an instruction changes
+/// the register.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Watchpoint {
+    Memory {
+        range: RangeInclusive<u16>,
+        access: Access,
+    },
+    Register(Register),
+}
+
+impl Watchpoint {
+    pub(crate) fn watches(&self, address: u16, access: Access) -> bool {
+        match self {
+            Watchpoint::Memory {
+                range,
+                access: watched,
+            } => range.contains(&address) && watched.includes(access),
+            Watchpoint::Register(_) => false,
+        }
+    }
+}
+
+impl fmt::Display for Watchpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Watchpoint::Memory { range, access } => {
+                let access = match access {
+                    Access::Read => "read",
+                    Access::Write => "write",
+                    Access::ReadWrite => "access",
+                };
+                write!(f, "{} {:#05X}", access, range.start())?;
+                if range.end() != range.start() {
+                    write!(f, "-{:#05X}", range.end())?;
+                }
+                Ok(())
+            }
+            Watchpoint::Register(register) => write!(f, "change {}", register),
+        }
+    }
+}
+
+/// What a watched access touched.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchTarget {
+    Memory(u16),
+    Register(Register),
+}
+
+/// An access to something being watched.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WatchHit {
+    /// Address of the instruction that made the access.
+    pub pc: u16,
+    pub target: WatchTarget,
+
+    /// Read or Write.
+    pub access: Access,
+
+    /// The value before and after the instruction, which are the same for reads.
+    pub old: u16,
+    pub new: u16,
+}
+
+impl fmt::Display for WatchHit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:#05X} ", self.pc)?;
+        match (self.target, self.access) {
+            (WatchTarget::Memory(address), Access::Read) => {
+                write!(f, "read {:#05X}: {:#04X}", address, self.new)
+            }
+            (WatchTarget::Memory(address), _) => write!(
+                f,
+                "wrote {:#05X}: {:#04X} -> {:#04X}",
+                address, self.old, self.new
+            ),
+            (WatchTarget::Register(Register::I), _) => {
+                write!(f, "changed I: {:#05X} -> {:#05X}", self.old, self.new)
+            }
+            (WatchTarget::Register(register), _) => write!(
+                f,
+                "changed {}: {:#04X} -> {:#04X}",
+                register, self.old, self.new
+            ),
+        }
+    }
+}
@@ -0,0 +1,166 @@
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use chip8::gdb::{GdbStub, Resume, StopReason};
+use chip8::system::{StepOutcome, System};
+
+/// Serves `program` to one debugger, running it like a front end would, and returns the
+/// connected client along with the way the session ended.
+fn attach(program: &'static [u8]) -> (TcpStream, JoinHandle<Resume>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+
+    let server = thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let mut stub = GdbStub::new(stream).unwrap();
+        let mut system = System::default();
+        system.load_rom(program).unwrap();
+
+        loop {
+            match stub.serve(&mut system).unwrap() {
+                Resume::Continue => {}
+                resume => return resume,
+            }
+            let reason = loop {
+                match system.run_cycles(100) {
+                    Ok(StepOutcome::Breakpoint) => break StopReason::Breakpoint,
+                    Ok(StepOutcome::Watchpoint) => {
+                        break StopReason::Watchpoint(system.watch_hits()[0])
+                    }
+                    Ok(StepOutcome::Exited) => break StopReason::Exited,
+                    Err(e) => break StopReason::Error(e),
+                    Ok(_) if stub.interrupted().unwrap() => break StopReason::Interrupt,
+                    Ok(_) => {}
+                }
+            };
+            stub.stopped(&reason).unwrap();
+        }
+    });
+
+    let client = TcpStream::connect(address).unwrap();
+    client.set_nodelay(true).unwrap();
+    // Fail instead of hanging when the stub does not answer.
+    client
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    (client, server)
+}
+
+fn read_byte(stream: &mut TcpStream) -> u8 {
+    let mut byte = [0];
+    stream.read_exact(&mut byte).unwrap();
+    byte[0]
+}
+
+fn checksum(data: &str) -> u8 {
+    data.bytes().fold(0, |sum, byte| sum.wrapping_add(byte))
+}
+
+/// Reads and acknowledges the next packet from the stub.
+fn reply(stream: &mut TcpStream) -> String {
+    assert_eq!(read_byte(stream), b'$');
+    let mut data = String::new();
+    loop {
+        match read_byte(stream) {
+            b'#' => break,
+            byte => data.push(byte as char),
+        }
+    }
+    let sum = [read_byte(stream), read_byte(stream)];
+    assert_eq!(
+        std::str::from_utf8(&sum).unwrap(),
+        format!("{:02x}", checksum(&data))
+    );
+
+    stream.write_all(b"+").unwrap();
+    data
+}
+
+/// Sends a packet, checks the stub acknowledged it, and returns its reply.
+fn send(stream: &mut TcpStream, data: &str) -> String {
+    write!(stream, "${}#{:02x}", data, checksum(data)).unwrap();
+    assert_eq!(read_byte(stream), b'+');
+    reply(stream)
+}
+
+#[test]
+fn test_inspects_and_controls_the_program() {
+    let (mut client, server) = attach(&[
+        0x60, 0x2A, // LD V0, 0x2A
+        0xA3, 0x00, // LD I, 0x300
+        0x70, 0x01, // ADD V0, 1
+        0x12, 0x04, // JP 0x204
+    ]);
+
+    assert!(send(&mut client, "qSupported:multiprocess+").contains("qXfer:features:read+"));
+    let description = send(&mut client, "qXfer:features:read:target.xml:0,fff");
+    assert!(description.starts_with("l<?xml"));
+    assert!(description.contains("<reg name=\"pc\" bitsize=\"16\" type=\"code_ptr\"/>"));
+    assert_eq!(send(&mut client, "?"), "S05");
+
+    assert_eq!(send(&mut client, "Z0,206,2"), "OK");
+    assert_eq!(send(&mut client, "c"), "S05");
+    assert_eq!(
+        send(&mut client, "g"),
+        format!("2b{}00030602000000", "00".repeat(15))
+    );
+
+    assert_eq!(send(&mut client, "P11=0402"), "OK");
+    assert_eq!(send(&mut client, "p11"), "0402");
+    assert_eq!(send(&mut client, "P12=01"), "E01");
+
+    assert_eq!(send(&mut client, "m200,4"), "602aa300");
+    assert_eq!(send(&mut client, "M300,2:beef"), "OK");
+    assert_eq!(send(&mut client, "m300,2"), "beef");
+    assert_eq!(send(&mut client, "mfff,2"), "E01");
+    assert_eq!(send(&mut client, "mffffffffffffffff,1"), "E01");
+    assert_eq!(send(&mut client, "Mffffffffffffffff,1:00"), "E01");
+    assert_eq!(
+        send(
+            &mut client,
+            "qXfer:features:read:target.xml:0,ffffffffffffffff"
+        ),
+        description
+    );
+    assert_eq!(
+        send(
+            &mut client,
+            "qXfer:features:read:target.xml:1,ffffffffffffffff"
+        ),
+        format!("l{}", &description[2..])
+    );
+
+    assert_eq!(send(&mut client, "z0,206,2"), "OK");
+    assert_eq!(send(&mut client, "s"), "S05");
+    assert_eq!(send(&mut client, "p0"), "2c");
+    assert_eq!(send(&mut client, "p11"), "0602");
+
+    assert_eq!(send(&mut client, "Z2,300,2"), "OK");
+    assert_eq!(send(&mut client, "M200,2:f155"), "OK");
+    assert_eq!(send(&mut client, "c200"), "T05watch:300;");
+    assert_eq!(send(&mut client, "p11"), "0202");
+    assert_eq!(send(&mut client, "z2,300,2"), "OK");
+
+    assert_eq!(send(&mut client, "vMustReplyEmpty"), "");
+    assert_eq!(send(&mut client, "D"), "OK");
+    assert_eq!(server.join().unwrap(), Resume::Detach);
+}
+
+#[test]
+fn test_interrupts_running_program() {
+    let (mut client, server) = attach(&[0x12, 0x00]);
+
+    // Packets with a bad checksum are asked for again.
+    client.write_all(b"$?#00").unwrap();
+    assert_eq!(read_byte(&mut client), b'-');
+
+    assert_eq!(send(&mut client, "QStartNoAckMode"), "OK");
+    client.write_all(b"$c#63").unwrap();
+    client.write_all(&[0x03]).unwrap();
+    assert_eq!(reply(&mut client), "S02");
+
+    client.write_all(b"$k#6b").unwrap();
+    assert_eq!(server.join().unwrap(), Resume::Kill);
+}
@@ -1,6 +1,7 @@
 use rstest::*;
 
-use chip8::opcode::{decode, Operation};
+use chip8::error::EncodeError;
+use chip8::opcode::{decode, encode, Operation};
 
 #[rstest]
 #[case(0x0000, Operation::NoOperation)]
@@ -37,7 +38,63 @@ use chip8::opcode::{decode, Operation};
 #[case(0xF033, Operation::StoreBinaryCodedDecimal { x: 0 })]
 #[case(0xF055, Operation::StoreRegistersInMemory { x: 0 })]
 #[case(0xF065, Operation::SetRegistersFromMemory { x: 0 })]
-fn test_decode(#[case] opcode: u16, #[case] _expected: Operation) {
+#[case(0x00C5, Operation::ScrollDown { n: 5 })]
+#[case(0x00FB, Operation::ScrollRight)]
+#[case(0x00FC, Operation::ScrollLeft)]
+#[case(0x00FD, Operation::Exit)]
+#[case(0x00FE, Operation::LowResolution)]
+#[case(0x00FF, Operation::HighResolution)]
+#[case(0xF030, Operation::SetIndexToLargeSprite { x: 0 })]
+#[case(0xF075, Operation::StoreRegistersInFlags { x: 0 })]
+#[case(0xF085, Operation::SetRegistersFromFlags { x: 0 })]
+#[case(0x00D3, Operation::ScrollUp { n: 3 })]
+#[case(0x5122, Operation::StoreRegisterRange { x: 1, y: 2 })]
+#[case(0x5213, Operation::LoadRegisterRange { x: 2, y: 1 })]
+#[case(0xF000, Operation::SetIndexToLongAddress)]
+#[case(0xF201, Operation::SelectPlanes { x: 2 })]
+#[case(0xF002, Operation::StoreAudioPattern)]
+#[case(0xF03A, Operation::SetPitch { x: 0 })]
+fn test_decode(#[case] opcode: u16, #[case] expected: Operation) {
     let operation = decode(opcode).unwrap();
-    assert!(matches!(operation, _expected));
+    assert_eq!(operation, expected);
+}
+
+#[rstest]
+#[case(0x5121)]
+#[case(0xF100)]
+#[case(0xF102)]
+#[case(0x0100)]
+#[case(0x01E0)]
+#[case(0x9001)]
+fn test_decode_invalid(#[case] opcode: u16) {
+    assert_eq!(decode(opcode), Err(()));
+}
+
+#[test]
+fn test_encode_round_trips_every_opcode() {
+    let mut valid = 0;
+    for word in 0..=u16::MAX {
+        if let Ok(operation) = decode(word) {
+            assert_eq!(encode(&operation), Ok(word), "{:?}", operation);
+            assert_eq!(u16::try_from(operation), Ok(word));
+            valid += 1;
+        }
+    }
+    assert_eq!(valid, 44_586);
+}
+
+#[rstest]
+#[case(Operation::GotoAddress { nnn: 0x1000 }, "nnn", 0x1000, 0xFFF)]
+#[case(Operation::SetRegister { x: 16, nn: 0 }, "x", 16, 0xF)]
+#[case(Operation::BitwiseOr { x: 0, y: 0x20 }, "y", 0x20, 0xF)]
+#[case(Operation::DrawSprite { x: 0, y: 0, n: 16 }, "n", 16, 0xF)]
+#[case(Operation::ScrollDown { n: 0xFF }, "n", 0xFF, 0xF)]
+fn test_encode_rejects_out_of_range_fields(
+    #[case] operation: Operation,
+    #[case] field: &'static str,
+    #[case] value: u16,
+    #[case] max: u16,
+) {
+    let error = encode(&operation).unwrap_err();
+    assert_eq!(error, EncodeError { field, value, max });
 }
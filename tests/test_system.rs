@@ -0,0 +1,533 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::Duration;
+
+use rstest::*;
+
+use chip8::assembler::assemble;
+use chip8::config::Config;
+use chip8::error::{ExecutionError, RomError};
+use chip8::input_output::Headless;
+use chip8::profile::Profiler;
+use chip8::system::{StepOutcome, System};
+use chip8::trace::{TraceEntry, Tracer};
+use chip8::watchpoint::{Access, Register, WatchHit, WatchTarget, Watchpoint};
+
+/// Calls itself forever, nesting one level deeper every step.
+const RECURSE_PROGRAM: [u8; 2] = [0x22, 0x00];
+
+/// Draws the "0" glyph at random positions forever.
+const RANDOM_DRAW_PROGRAM: [u8; 10] = [
+    0xC0, 0x3F, // RND V0, 0x3F
+    0xC1, 0x1F, // RND V1, 0x1F
+    0xA0, 0x50, // LD I, 0x050
+    0xD0, 0x15, // DRW V0, V1, 5
+    0x12, 0x00, // JP 0x200
+];
+
+/// Clears the screen and draws the "0" glyph stored after the code at (0, 0).
+const DRAW_PROGRAM: [u8; 15] = [
+    0x00, 0xE0, // CLS
+    0xA2, 0x0A, // LD I, 0x20A
+    0x60, 0x00, // LD V0, 0
+    0x61, 0x00, // LD V1, 0
+    0xD0, 0x15, // DRW V0, V1, 5
+    0xF0, 0x90, 0x90, 0x90, 0xF0,
+];
+
+#[test]
+fn test_headless_step_draws_sprite() {
+    let mut system = System::default();
+    system.load_rom(&DRAW_PROGRAM).unwrap();
+
+    for _ in 0..5 {
+        system.step().unwrap();
+    }
+
+    let framebuffer = system.framebuffer();
+    assert_eq!(framebuffer.get(0, 0), 1);
+    assert_eq!(framebuffer.get(3, 0), 1);
+    assert_eq!(framebuffer.get(1, 1), 0);
+    assert_eq!(framebuffer.get(4, 0), 0);
+    assert!(system.draw_flag);
+
+    system.draw();
+    assert_eq!(system.io().frames_presented, 1);
+    assert_eq!(system.io().frame.as_ref(), Some(system.framebuffer()));
+}
+
+#[rstest]
+#[case(Duration::ZERO, 30)]
+#[case(Duration::from_millis(16), 30)]
+#[case(Duration::from_millis(17), 29)]
+#[case(Duration::from_millis(100), 24)]
+#[case(Duration::from_secs(1), 0)]
+fn test_timers_count_down_at_60hz(#[case] elapsed: Duration, #[case] expected: u8) {
+    let mut system = System::default();
+    system
+        .load_rom(&[
+            0x60, 0x1E, // LD V0, 30
+            0xF0, 0x15, // LD DT, V0
+            0xF0, 0x18, // LD ST, V0
+        ])
+        .unwrap();
+    for _ in 0..3 {
+        system.step().unwrap();
+    }
+    assert!(system.io().beeping);
+
+    system.update_timers(elapsed);
+
+    assert_eq!(system.delay_timer(), expected);
+    assert_eq!(system.sound_timer(), expected);
+    assert_eq!(system.sound_active(), expected > 0);
+    assert_eq!(system.io().beeping, expected > 0);
+}
+
+#[test]
+fn test_timer_ticks_accumulate_across_updates() {
+    let mut system = System::default();
+    system
+        .load_rom(&[0x60, 0x0A, 0xF0, 0x15, 0xF1, 0x07])
+        .unwrap();
+    system.step().unwrap();
+    system.step().unwrap();
+
+    for _ in 0..10 {
+        system.update_timers(Duration::from_millis(5));
+    }
+    system.step().unwrap();
+
+    assert_eq!(system.delay_timer(), 7);
+}
+
+#[test]
+fn test_pong_runs() {
+    let mut system = System::default();
+    system.load_rom_from_file("games/pong.ch8").unwrap();
+
+    for _ in 0..20_000 {
+        system.step().unwrap();
+        system.tick_timers();
+    }
+
+    assert!(system.framebuffer().pixels().iter().any(|p| *p > 0));
+}
+
+#[test]
+fn test_draws_font_glyph() {
+    let mut system = System::default();
+    system
+        .load_rom(&[
+            0x60, 0x01, // LD V0, 1
+            0xF0, 0x29, // LD F, V0
+            0xD1, 0x15, // DRW V1, V1, 5
+        ])
+        .unwrap();
+    for _ in 0..3 {
+        system.step().unwrap();
+    }
+
+    let rows: Vec<Vec<u8>> = (0..5)
+        .map(|y| (0..4).map(|x| system.framebuffer().get(x, y)).collect())
+        .collect();
+    assert_eq!(
+        rows,
+        [
+            [0, 0, 1, 0],
+            [0, 1, 1, 0],
+            [0, 0, 1, 0],
+            [0, 0, 1, 0],
+            [0, 1, 1, 1],
+        ]
+    );
+}
+
+#[rstest]
+#[case(&[0x60, 0x00, 0x01, 0x23], ExecutionError::InvalidOpcode { address: 0x202, word: 0x0123 })]
+#[case(&[0x60, 0x00, 0x80, 0x0F], ExecutionError::InvalidOpcode { address: 0x202, word: 0x800F })]
+#[case(&[0x00, 0xEE], ExecutionError::StackUnderflow { address: 0x200 })]
+#[case(&[0x22, 0x00], ExecutionError::StackOverflow { address: 0x200 })]
+#[case(&[0x1F, 0xFF], ExecutionError::ProgramCounterOutOfBounds { address: 0xFFF })]
+#[case(&[0xAF, 0xFE, 0xF0, 0x33], ExecutionError::MemoryOutOfBounds { address: 0x202, target: 0x1000 })]
+#[case(&[0xAF, 0xFF, 0xF1, 0x55], ExecutionError::MemoryOutOfBounds { address: 0x202, target: 0x1000 })]
+#[case(&[0xAF, 0xFF, 0xF1, 0x65], ExecutionError::MemoryOutOfBounds { address: 0x202, target: 0x1000 })]
+#[case(&[0xAF, 0xFC, 0xD0, 0x05], ExecutionError::MemoryOutOfBounds { address: 0x202, target: 0x1000 })]
+fn test_step_errors(#[case] program: &[u8], #[case] expected: ExecutionError) {
+    let mut system = System::default();
+    system.load_rom(program).unwrap();
+
+    let error = (0..100)
+        .map(|_| system.step())
+        .find_map(Result::err)
+        .expect("program should fail");
+
+    assert_eq!(error, expected);
+}
+
+#[test]
+fn test_step_reports_waiting_for_key() {
+    let mut system = System::default();
+    system.load_rom(&[0xF0, 0x0A]).unwrap();
+
+    assert_eq!(system.step(), Ok(StepOutcome::Waiting));
+    system.keypad_mut().press(1);
+    assert_eq!(system.step(), Ok(StepOutcome::Waiting));
+    system.keypad_mut().release(1);
+    assert_eq!(system.step(), Ok(StepOutcome::Executed));
+}
+
+#[rstest]
+#[case(0x200, 3584)]
+#[case(0x600, 2560)]
+fn test_load_rom_rejects_oversized_images(#[case] load_address: u16, #[case] capacity: usize) {
+    let config = Config {
+        load_address,
+        ..Config::default()
+    };
+    let mut system = System::with_config(Headless::default(), config).unwrap();
+
+    assert!(system.load_rom(&vec![0xAA; capacity]).is_ok());
+    assert!(matches!(
+        system.load_rom(&vec![0xAA; capacity + 1]),
+        Err(RomError::TooLarge { size, capacity: c }) if size == capacity + 1 && c == capacity
+    ));
+    assert!(matches!(
+        system.load_rom_from_reader(&vec![0xAA; 10_000][..]),
+        Err(RomError::TooLarge { size, .. }) if size == capacity + 1
+    ));
+}
+
+#[test]
+fn test_load_rom_rejects_empty_images() {
+    let mut system = System::default();
+
+    assert!(matches!(system.load_rom(&[]), Err(RomError::Empty)));
+    assert!(matches!(
+        system.load_rom_from_reader(std::io::empty()),
+        Err(RomError::Empty)
+    ));
+}
+
+#[test]
+fn test_load_rom_reports_io_errors() {
+    let mut system = System::default();
+
+    assert!(matches!(
+        system.load_rom_from_file("games/does-not-exist.ch8"),
+        Err(RomError::Io(_))
+    ));
+}
+
+#[test]
+fn test_rom_runs_from_load_address() {
+    let config = Config {
+        load_address: 0x600,
+        ..Config::default()
+    };
+    let mut system = System::with_config(Headless::default(), config).unwrap();
+    system
+        .load_rom(&[
+            0x00, 0xE0, 0xA6, 0x0A, 0x60, 0x00, 0x61, 0x00, 0xD0, 0x15, 0xF0, 0x90, 0x90, 0x90,
+            0xF0,
+        ])
+        .unwrap();
+
+    for _ in 0..5 {
+        system.step().unwrap();
+    }
+
+    assert_eq!(system.framebuffer().get(0, 0), 1);
+}
+
+#[rstest]
+#[case(Config::default(), 16)]
+#[case(Config::vip(), 12)]
+#[case(Config { stack_depth: 64, ..Config::default() }, 64)]
+fn test_stack_depth(#[case] config: Config, #[case] depth: usize) {
+    let mut system = System::with_config(Headless::default(), config).unwrap();
+    system.load_rom(&RECURSE_PROGRAM).unwrap();
+
+    for _ in 0..depth {
+        assert_eq!(system.step(), Ok(StepOutcome::Executed));
+    }
+    assert_eq!(
+        system.step(),
+        Err(ExecutionError::StackOverflow { address: 0x200 })
+    );
+}
+
+#[test]
+fn test_save_state_resumes_where_it_left_off() {
+    let path = std::env::temp_dir().join(format!("chip8-{}.ch8.s1", std::process::id()));
+    let mut system = System::default();
+    system.load_rom(&DRAW_PROGRAM).unwrap();
+    system.keypad_mut().press(0x3);
+    for _ in 0..2 {
+        system.step().unwrap();
+    }
+    system.save_state_to_file(&path).unwrap();
+    for _ in 0..3 {
+        system.step().unwrap();
+    }
+
+    let mut restored = System::new(Headless::default());
+    restored.load_state_from_file(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert!(restored.keypad().is_pressed(0x3));
+    for _ in 0..3 {
+        restored.step().unwrap();
+    }
+
+    assert_eq!(restored.save_state(), system.save_state());
+    assert_eq!(restored.framebuffer().get(0, 0), 1);
+}
+
+#[test]
+fn test_rewind_steps_back_through_frames() {
+    let mut system = System::default();
+    system.set_rewind_capacity(2);
+    system.load_rom(&DRAW_PROGRAM).unwrap();
+
+    let mut frames = Vec::new();
+    for _ in 0..5 {
+        system.step().unwrap();
+        system.tick_timers();
+        frames.push(system.save_state());
+    }
+    assert_eq!(system.rewind_len(), 2);
+
+    assert!(system.rewind_frame());
+    assert_eq!(system.save_state(), frames[4]);
+    assert!(system.rewind_frame());
+    assert_eq!(system.save_state(), frames[3]);
+    assert!(!system.rewind_frame());
+
+    // Execution carries on from the rewound frame.
+    system.step().unwrap();
+    assert_eq!(system.framebuffer().get(0, 0), 1);
+}
+
+#[test]
+fn test_seeded_runs_are_identical() {
+    let run = |seed| {
+        let config = Config {
+            seed: Some(seed),
+            ..Config::default()
+        };
+        let mut system = System::with_config(Headless::default(), config).unwrap();
+        system.load_rom(&RANDOM_DRAW_PROGRAM).unwrap();
+        for _ in 0..500 {
+            system.step().unwrap();
+        }
+        system.framebuffer().clone()
+    };
+
+    assert_eq!(run(7), run(7));
+    assert_ne!(run(7), run(8));
+}
+
+#[test]
+fn test_run_frame_ticks_and_presents_once() {
+    let mut system = System::default();
+    system
+        .load_rom(&[
+            0x60, 0x0A, // LD V0, 10
+            0xF0, 0x15, // LD DT, V0
+            0xA0, 0x50, // LD I, 0x050
+            0xD0, 0x05, // DRW V0, V0, 5
+            0xD0, 0x05, // DRW V0, V0, 5
+            0x12, 0x0A, // JP 0x20A
+        ])
+        .unwrap();
+
+    assert_eq!(system.run_frame(5), Ok(StepOutcome::Executed));
+    assert_eq!(system.delay_timer(), 9);
+    assert_eq!(system.io().frames_presented, 1);
+    assert!(!system.draw_flag);
+
+    // Nothing new is drawn, so the frame is not presented again.
+    system.run_frame(1000).unwrap();
+    assert_eq!(system.delay_timer(), 8);
+    assert_eq!(system.io().frames_presented, 1);
+}
+
+#[test]
+fn test_runs_assembled_program() {
+    // Adds 2 to V1 three times, then draws the digit in V1.
+    let rom = assemble(
+        "        LD V0, 3
+         loop:   ADD V1, 2
+                 ADD V0, 0xFF
+                 SE V0, 0
+                 JP loop
+                 LD F, V1
+                 LD V2, 0
+                 DRW V2, V2, 5
+         done:   JP done",
+    )
+    .unwrap();
+
+    let mut system = System::default();
+    system.load_rom(&rom).unwrap();
+    system.run_frame(100).unwrap();
+
+    // The 6 glyph is 0xF0, 0x80, 0xF0, 0x90, 0xF0.
+    let framebuffer = system.io().frame.as_ref().unwrap();
+    let rows: Vec<_> = (0..5)
+        .map(|y| (0..4).map(|x| framebuffer.get(x, y)).collect::<Vec<_>>())
+        .collect();
+    assert_eq!(
+        rows,
+        [
+            [1, 1, 1, 1],
+            [1, 0, 0, 0],
+            [1, 1, 1, 1],
+            [1, 0, 0, 1],
+            [1, 1, 1, 1]
+        ]
+    );
+}
+
+#[test]
+fn test_breakpoints_stop_and_resume() {
+    let mut system = System::default();
+    system
+        .load_rom(&[
+            0x60, 0x00, // LD V0, 0
+            0x70, 0x01, // ADD V0, 1
+            0x12, 0x02, // JP 0x202
+        ])
+        .unwrap();
+    system.add_breakpoint(0x202);
+
+    assert_eq!(system.run_frame(100), Ok(StepOutcome::Breakpoint));
+    assert_eq!(system.program_counter(), 0x202);
+    assert_eq!(system.registers()[0], 0);
+    assert_eq!(system.delay_timer(), 0);
+
+    // Resuming runs the instruction at the breakpoint, then stops at it again.
+    assert_eq!(system.run_frame(100), Ok(StepOutcome::Breakpoint));
+    assert_eq!(system.registers()[0], 1);
+
+    assert!(system.remove_breakpoint(0x202));
+    assert_eq!(system.run_frame(10), Ok(StepOutcome::Executed));
+    assert_eq!(system.registers()[0], 6);
+}
+
+#[test]
+fn test_watchpoints_report_accesses() {
+    let mut system = System::default();
+    system
+        .load_rom(&[
+            0x60, 0x12, // LD V0, 0x12
+            0x61, 0x34, // LD V1, 0x34
+            0xA3, 0x00, // LD I, 0x300
+            0xF1, 0x55, // LD [I], V1
+            0xA3, 0x01, // LD I, 0x301
+            0xD0, 0x02, // DRW V0, V0, 2
+            0x12, 0x0C, // JP 0x20C
+        ])
+        .unwrap();
+    system.add_watchpoint(Watchpoint::Memory {
+        range: 0x301..=0x302,
+        access: Access::ReadWrite,
+    });
+    system.add_watchpoint(Watchpoint::Register(Register::V(0xF)));
+
+    assert_eq!(system.run_cycles(100), Ok(StepOutcome::Watchpoint));
+    assert_eq!(
+        system.watch_hits(),
+        [WatchHit {
+            pc: 0x206,
+            target: WatchTarget::Memory(0x301),
+            access: Access::Write,
+            old: 0x00,
+            new: 0x34,
+        }]
+    );
+
+    // The sprite reads both watched bytes, and collides with nothing.
+    assert_eq!(system.run_cycles(100), Ok(StepOutcome::Watchpoint));
+    let hits: Vec<_> = system
+        .watch_hits()
+        .iter()
+        .map(|hit| hit.to_string())
+        .collect();
+    assert_eq!(hits, ["0x20A read 0x301: 0x34", "0x20A read 0x302: 0x00"]);
+
+    system.set_program_counter(0x20A);
+    system.step().unwrap();
+    assert_eq!(
+        system.watch_hits().last().unwrap().to_string(),
+        "0x20A changed VF: 0x00 -> 0x01"
+    );
+
+    assert!(system.remove_watchpoint(&Watchpoint::Register(Register::V(0xF))));
+    system.step().unwrap();
+    assert_eq!(system.run_cycles(100), Ok(StepOutcome::Executed));
+}
+
+/// Collects every entry it is given.
+struct Collector(Rc<RefCell<Vec<TraceEntry>>>);
+
+impl Tracer for Collector {
+    fn trace(&mut self, entry: &TraceEntry) {
+        self.0.borrow_mut().push(*entry);
+    }
+}
+
+#[test]
+fn test_tracer_sees_every_instruction() {
+    let mut system = System::default();
+    system
+        .load_rom(&[
+            0x60, 0x01, // LD V0, 1
+            0x70, 0x01, // ADD V0, 1
+            0xFF, 0xFF, // invalid
+        ])
+        .unwrap();
+    let entries = Rc::new(RefCell::new(Vec::new()));
+    system.set_tracer(Box::new(Collector(entries.clone())));
+
+    system.step().unwrap();
+    system.step().unwrap();
+    assert!(system.step().is_err());
+    assert_eq!(system.ops(), 2);
+
+    let entries = entries.borrow();
+    let executed: Vec<_> = entries.iter().map(|entry| (entry.ops, entry.pc)).collect();
+    assert_eq!(executed, [(0, 0x200), (1, 0x202), (2, 0x204)]);
+    assert_eq!(entries[1].registers[0], 1);
+    assert_eq!(entries[2].opcode, 0xFFFF);
+
+    assert!(system.take_tracer().is_some());
+    system.reset();
+    assert_eq!(system.ops(), 0);
+}
+
+#[test]
+fn test_profiler_counts_subroutines() {
+    let mut system = System::default();
+    system
+        .load_rom(&[
+            0x22, 0x06, // CALL 0x206
+            0x22, 0x06, // CALL 0x206
+            0x12, 0x04, // JP 0x204
+            0x70, 0x01, // ADD V0, 1
+            0x00, 0xEE, // RET
+        ])
+        .unwrap();
+    system.set_profiler(Profiler::new());
+
+    for _ in 0..7 {
+        system.step().unwrap();
+    }
+
+    let profiler = system.take_profiler().unwrap();
+    assert_eq!(profiler.ops(), system.ops());
+    assert_eq!(profiler.ops(), 7);
+    assert_eq!(profiler.operations()["SubroutineCall"].executed, 2);
+    assert_eq!(profiler.addresses()[&0x206].1.executed, 2);
+    assert_eq!(profiler.collapsed().to_string(), "main 3\nmain;0x206 4\n");
+}